@@ -0,0 +1,169 @@
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::fs::File;
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+
+use mongodb::bson::{self, Bson};
+use serde_json::Value;
+use zip::write::FileOptions;
+use zip::{ZipArchive, ZipWriter};
+
+use crate::global::MemoryStoreQuestion;
+use crate::schema::migrate;
+
+/// 读取题库文件中的原始文档，支持mongoexport --jsonArray导出的.json文件和压缩后的.zip文件
+pub fn read_documents(path: &Path) -> Result<Vec<Value>, Box<dyn Error>> {
+    let content = if is_zip(path) {
+        let mut archive = ZipArchive::new(File::open(path)?)?;
+        let name = archive
+            .file_names()
+            .find(|name| name.ends_with(".json"))
+            .map(|name| name.to_string())
+            .ok_or_else(|| format!("no .json file in {}", path.display()))?;
+        let mut content = String::new();
+        archive.by_name(&name)?.read_to_string(&mut content)?;
+        content
+    } else {
+        std::fs::read_to_string(path)?
+    };
+    match serde_json::from_str::<Value>(&content)? {
+        Value::Array(documents) => Ok(documents),
+        _ => Err(format!("{} is not a json array", path.display()).into()),
+    }
+}
+
+/// 将一个mongodb extended json文档升级为当前的格式版本后解析为MemoryStoreQuestion，
+/// 所有存储读取题目时都经过这里
+pub fn parse_question(document: Value) -> Result<MemoryStoreQuestion, Box<dyn Error>> {
+    let bson = Bson::try_from(migrate(document)?)?;
+    Ok(bson::from_bson(bson)?)
+}
+
+/// 读取题库文件中的所有题目，任何一题解析失败都会返回错误
+pub fn read_bank(path: &Path) -> Result<Vec<MemoryStoreQuestion>, Box<dyn Error>> {
+    read_documents(path)?
+        .into_iter()
+        .enumerate()
+        .map(|(index, document)| {
+            parse_question(document).map_err(|error| {
+                format!("document {} in {}: {}", index, path.display(), error).into()
+            })
+        })
+        .collect()
+}
+
+/// 将题目按mongoexport --jsonArray的格式写入.json文件，或写入.zip文件中同名的.json。
+/// 先写入同目录下的临时文件再替换原文件，写到一半退出时原文件不会损坏。
+/// .zip中的.json与直接写入时同名，只有外层的文件名是临时的
+pub fn replace_bank(path: &Path, questions: &[MemoryStoreQuestion]) -> Result<(), Box<dyn Error>> {
+    let name = path
+        .file_name()
+        .map(|name| name.to_string_lossy().to_string())
+        .unwrap_or_default();
+    let temp = path.with_file_name(format!(".tmp-{}", name));
+    write_bank_file(&temp, path, questions)?;
+    std::fs::rename(&temp, path)?;
+    Ok(())
+}
+
+/// 写入file，格式和.zip中.json的名称按path决定
+fn write_bank_file(
+    file: &Path,
+    path: &Path,
+    questions: &[MemoryStoreQuestion],
+) -> Result<(), Box<dyn Error>> {
+    let documents = questions
+        .iter()
+        .map(|q| Ok(bson::to_bson(q)?.into_relaxed_extjson()))
+        .collect::<Result<Vec<Value>, Box<dyn Error>>>()?;
+    let content = serde_json::to_string_pretty(&documents)?;
+    if is_zip(path) {
+        let name = path
+            .file_stem()
+            .map(|stem| stem.to_string_lossy().to_string())
+            .unwrap_or_else(|| "questions".to_string())
+            + ".json";
+        let mut zip = ZipWriter::new(File::create(file)?);
+        zip.start_file(name, FileOptions::default())?;
+        zip.write_all(content.as_bytes())?;
+        zip.finish()?;
+    } else {
+        std::fs::write(file, content)?;
+    }
+    Ok(())
+}
+
+/// BANK为目录时按课程id选择其中的questions-<课程id>.zip，文件不存在时会在写回时创建；
+/// BANK为文件时直接使用
+pub fn select_bank(bank: &Path, course_id: &str) -> Result<PathBuf, Box<dyn Error>> {
+    if !bank.is_dir() {
+        return Ok(bank.to_path_buf());
+    }
+    if course_id.is_empty() {
+        return Err(format!(
+            "unable to tell which bank in {} to use, set COURSE_ID or point BANK at a bank file",
+            bank.display()
+        )
+        .into());
+    }
+    Ok(bank.join(format!("questions-{}.zip", course_id)))
+}
+
+/// 题库目录中的全部题库文件（.zip和.json），按文件名排序；path为文件时只有它自己
+pub fn bank_files(path: &Path) -> Result<Vec<PathBuf>, Box<dyn Error>> {
+    if !path.is_dir() {
+        return Ok(vec![path.to_path_buf()]);
+    }
+    let mut files = Vec::new();
+    for entry in std::fs::read_dir(path)? {
+        let file = entry?.path();
+        let is_json = file
+            .extension()
+            .is_some_and(|ext| ext.eq_ignore_ascii_case("json"));
+        if file.is_file() && (is_zip(&file) || is_json) {
+            files.push(file);
+        }
+    }
+    files.sort();
+    Ok(files)
+}
+
+/// questions-<课程id>.zip（或.json）中的课程id，其他文件名不对应课程
+pub fn course_id_of_bank(path: &Path) -> Option<String> {
+    let stem = path.file_stem()?.to_string_lossy().to_string();
+    match stem.strip_prefix("questions-") {
+        Some(course) if !course.is_empty() => Some(course.to_string()),
+        _ => None,
+    }
+}
+
+/// 题库文件对应的课程，questions-<课程id>.zip为课程id，其他文件为文件名
+pub fn course_of_bank(path: &Path) -> String {
+    course_id_of_bank(path).unwrap_or_else(|| {
+        path.file_stem()
+            .map(|stem| stem.to_string_lossy().to_string())
+            .unwrap_or_default()
+    })
+}
+
+/// 按所属的课程分组，写回bank/下各课程的题库，不知道课程的题目不在其中
+pub fn split_by_course(
+    questions: Vec<MemoryStoreQuestion>,
+) -> BTreeMap<String, Vec<MemoryStoreQuestion>> {
+    let mut courses: BTreeMap<String, Vec<MemoryStoreQuestion>> = BTreeMap::new();
+    for question in questions {
+        if !question.course_id.is_empty() {
+            courses
+                .entry(question.course_id.clone())
+                .or_default()
+                .push(question);
+        }
+    }
+    courses
+}
+
+fn is_zip(path: &Path) -> bool {
+    path.extension()
+        .is_some_and(|ext| ext.eq_ignore_ascii_case("zip"))
+}
@@ -0,0 +1,879 @@
+use std::collections::HashSet;
+use std::error::Error;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+
+use crate::bank::{
+    bank_files, course_id_of_bank, course_of_bank, parse_question, read_bank, read_documents,
+    replace_bank, select_bank, split_by_course,
+};
+use crate::config::{Config, ConfigArgs};
+use crate::credentials::extract;
+use crate::diff::{self, Change, Key};
+use crate::evidence;
+use crate::global::{GlobalData, MemoryStoreQuestion};
+use crate::guide::{self, Course};
+use crate::merge::{self, Bank, MergePolicy};
+use crate::request::{
+    is_session_expired, iteration_request, save_request, start_request, submit_request, SaveBody,
+};
+use crate::response::QuestionFormat;
+use crate::session::reauthenticate;
+use crate::solver::{self, Solution};
+use crate::store::{self, collapse_duplicates, QuestionStore};
+use crate::verify::{self, isolate};
+
+/// 发送请求，会话过期时等待重新登录后重新发送同一个请求
+macro_rules! with_session {
+    ($args:expr, $config:expr, $global_data:expr, $request:expr) => {
+        loop {
+            match $request.await {
+                Err(error) if is_session_expired(error.as_ref()) => {
+                    reauthenticate($args, $config, $global_data, error.as_ref()).await?
+                }
+                result => break result?,
+            }
+        }
+    };
+}
+
+/// 循环答题，配置了BANK时启动时将题库读入存储，结束时（包括Ctrl-C）将更新后的题库写回。
+/// quarantine时答题前隔离自相矛盾的记录，否则只报告
+pub async fn run(
+    args: &ConfigArgs,
+    config: &Config,
+    quarantine: bool,
+) -> Result<(), Box<dyn Error>> {
+    let mut config = config.clone();
+    let store = store::open(&config).await?;
+    let bank = if config.bank.is_empty() {
+        None
+    } else {
+        Some(select_bank(Path::new(&config.bank), &config.course_id)?)
+    };
+    let mut keys = match &bank {
+        Some(path) => load_bank(path, &config.course_id, store.as_ref()).await?,
+        None => Vec::new(),
+    };
+    // 自相矛盾的记录默认只报告，答题时当作未知的题目，不会在答题中途出错
+    let inconsistent = check_store(&config, store.as_ref(), quarantine).await?;
+    if inconsistent > 0 && !quarantine {
+        println!(
+            "\x1b[33m{} inconsistent questions are answered as unknown, \
+             run verify --quarantine to set them aside\x1b[0m",
+            inconsistent
+        );
+    }
+
+    let result = tokio::select! {
+        result = answer(args, &mut config, store.as_ref(), &mut keys) => result,
+        _ = tokio::signal::ctrl_c() => {
+            println!("\x1b[36minterrupted, stop answering\x1b[0m");
+            Ok(())
+        }
+    };
+    if let Some(path) = bank {
+        write_back_bank(&path, store.as_ref(), keys).await?;
+    }
+    result
+}
+
+/// 检查存储中的全部题目并逐题报告问题，返回有问题的题目数量。
+/// quarantine时将有问题的记录原样追加到QUARANTINE_PATH，存储中只保留题目本身
+async fn check_store(
+    config: &Config,
+    store: &dyn QuestionStore,
+    quarantine: bool,
+) -> Result<usize, Box<dyn Error>> {
+    let mut inconsistent = Vec::new();
+    for question in store.all().await? {
+        let problems = verify::verify(&question);
+        if problems.is_empty() {
+            continue;
+        }
+        for problem in problems.iter() {
+            println!(
+                "\x1b[31m[{}] in {} {}\x1b[0m",
+                question.useful_id, question.locale, problem
+            );
+        }
+        println!("    {}", serde_json::to_string(&question)?);
+        inconsistent.push(question);
+    }
+    if quarantine && !inconsistent.is_empty() {
+        let path = Path::new(&config.quarantine_path);
+        // 先写隔离文件再修改存储，中途出错也不会丢失原记录
+        verify::quarantine(path, &inconsistent)?;
+        let attempts = store.attempts().await?;
+        let isolated = inconsistent
+            .iter()
+            .map(|q| isolate(q, &attempts))
+            .collect::<Vec<_>>();
+        store.save_all(&isolated).await?;
+        println!(
+            "quarantined {} inconsistent questions into {}",
+            inconsistent.len(),
+            path.display()
+        );
+    }
+    Ok(inconsistent.len())
+}
+
+/// 检查存储中的题目是否自洽
+pub async fn verify(config: &Config, quarantine: bool) -> Result<(), Box<dyn Error>> {
+    let store = store::open(config).await?;
+    let count = check_store(config, store.as_ref(), quarantine).await?;
+    if count == 0 || quarantine {
+        println!("{} inconsistent questions found in the store", count);
+        Ok(())
+    } else {
+        Err(format!(
+            "{} inconsistent questions in the store, run verify --quarantine to set them aside",
+            count
+        )
+        .into())
+    }
+}
+
+/// 将题库中的题目合并进存储，返回题库中所有题目的useful_id和locale。
+/// 不知道课程的题目归入题库文件名中的课程，文件名不含课程时归入course_id
+async fn load_bank(
+    path: &Path,
+    course_id: &str,
+    store: &dyn QuestionStore,
+) -> Result<Vec<(String, String)>, Box<dyn Error>> {
+    if !path.exists() {
+        println!(
+            "{} does not exist yet, it will be created at shutdown",
+            path.display()
+        );
+        return Ok(Vec::new());
+    }
+    let course_id = course_id_of_bank(path).unwrap_or_else(|| course_id.to_string());
+    let mut questions = Vec::new();
+    for mut question in read_bank(path)? {
+        question.fill_namespace(&course_id, "", "");
+        let merged = match store.find(&question.useful_id, &question.locale).await? {
+            Some(mut stored) => {
+                stored.merge_knowledge(&question);
+                stored
+            }
+            None => question,
+        };
+        questions.push(merged);
+    }
+    store.save_all(&questions).await?;
+    println!(
+        "loaded {} questions from {}",
+        questions.len(),
+        path.display()
+    );
+    Ok(questions
+        .into_iter()
+        .map(|q| (q.useful_id, q.locale))
+        .collect())
+}
+
+/// 将原题库中的题目和本次答过的题目以存储中的最新记录写回题库
+async fn write_back_bank(
+    path: &Path,
+    store: &dyn QuestionStore,
+    mut keys: Vec<(String, String)>,
+) -> Result<(), Box<dyn Error>> {
+    let mut seen = HashSet::new();
+    keys.retain(|key| seen.insert(key.clone()));
+    let mut questions = Vec::new();
+    for (useful_id, locale) in keys.iter() {
+        if let Some(question) = store.find(useful_id, locale).await? {
+            questions.push(question);
+        }
+    }
+    replace_bank(path, &questions)?;
+    println!("wrote {} questions to {}", questions.len(), path.display());
+    Ok(())
+}
+
+/// 不断地答题，每次答完后将题目的useful_id和locale加入answered
+async fn answer(
+    args: &ConfigArgs,
+    config: &mut Config,
+    store: &dyn QuestionStore,
+    answered: &mut Vec<(String, String)>,
+) -> Result<(), Box<dyn Error>> {
+    let mut global_data = GlobalData::from_config(config);
+    let client: reqwest::Client = reqwest::Client::new();
+
+    loop {
+        answer_iteration(args, config, store, &client, &mut global_data, answered).await?;
+    }
+}
+
+/// 答一次题：取得题目，逐题作答并提交，保存答题记录和由此得到的了解
+async fn answer_iteration(
+    args: &ConfigArgs,
+    config: &mut Config,
+    store: &dyn QuestionStore,
+    client: &reqwest::Client,
+    global_data: &mut GlobalData,
+    answered: &mut Vec<(String, String)>,
+) -> Result<(), Box<dyn Error>> {
+    /*iteration*/
+    let iteration_result = with_session!(
+        args,
+        config,
+        global_data,
+        iteration_request(client, global_data)
+    );
+    let student_assessment_iteration = iteration_result
+        .clone()
+        .student_assessment_iteration
+        .ok_or("the server returned no assessment iteration")?;
+    if student_assessment_iteration.questions.is_empty() {
+        return Err("the assessment iteration has no questions".into());
+    }
+    global_data.set_student_guid(student_assessment_iteration.student_assessment_sys_guid);
+    global_data.set_quiz_sys_guid(student_assessment_iteration.quiz_sys_guid);
+    student_assessment_iteration.questions.iter().for_each(|q| {
+        global_data.insert_question(q.student_assessment_question_sys_guid.clone());
+    });
+    println!(
+        "\x1b[30mget {} question\x1b[0m",
+        global_data.question_count()
+    );
+
+    /*start*/
+    println!("\x1b[30mrequest question choices\x1b[0m");
+    let start_result = with_session!(
+        args,
+        config,
+        global_data,
+        start_request(client, global_data.first_question(), global_data)
+    );
+    global_data.set_details(global_data.first_question(), start_result.details());
+    global_data.set_useful_id(global_data.first_question(), start_result.question_id);
+    global_data.set_format(global_data.first_question(), start_result.format);
+    global_data.set_choices(global_data.first_question(), start_result.answer_choices);
+    global_data
+        .update_from_db(store, global_data.first_question())
+        .await?;
+
+    /*save and next*/
+    // 只有一题时第一题就是最后一题，直接提交
+    for i in 1..global_data.question_count() {
+        let current = global_data.get_question_id(i - 1);
+        let next = global_data.get_question_id(i);
+        global_data.select_choices(current.clone());
+        let body = SaveBody::from(global_data.chosen_choices(current.clone()), next.clone());
+        println!("\x1b[30msend a question answer\x1b[0m");
+        let save_result = with_session!(
+            args,
+            config,
+            global_data,
+            save_request(client, current.clone(), global_data, &body)
+        );
+        global_data.set_details(next.clone(), save_result.details());
+        global_data.set_useful_id(next.clone(), save_result.question_id);
+        global_data.set_format(next.clone(), save_result.format);
+        global_data.set_choices(next.clone(), save_result.answer_choices);
+        global_data.update_from_db(store, next.clone()).await?;
+    }
+
+    /*submit*/
+    let last_question_id = global_data.last_question();
+    global_data.select_choices(last_question_id.clone());
+    let body = SaveBody::from(
+        global_data.chosen_choices(last_question_id.clone()),
+        last_question_id.clone(),
+    );
+    println!("\x1b[30msend a question answer\x1b[0m");
+    let submit_result = with_session!(
+        args,
+        config,
+        global_data,
+        submit_request(client, last_question_id.clone(), global_data, &body)
+    );
+    println!(
+        "get score({}), correct({}), already know correct({})",
+        submit_result.score,
+        submit_result.questions_correct,
+        global_data.get_min_correct()
+    );
+
+    // 先保存答题记录，存储中的了解引用的答题一定存在
+    let attempt = global_data.attempt(submit_result.score, submit_result.questions_correct);
+    store.record_attempt(&attempt).await?;
+
+    if submit_result.score == 100 {
+        println!("\x1b[36mtry to remember correct choices\x1b[0m");
+        global_data.remember_correct();
+    } else if global_data.get_min_correct() == submit_result.questions_correct {
+        println!("\x1b[36mtry to remember error choices\x1b[0m");
+        global_data.remember_error();
+    }
+
+    global_data.store_into_db(store).await?;
+    // 本次的得分单独推不出的，结合之前的答题可能推得出
+    let solution = solve_store(store).await?;
+    if !solution.learned.is_empty() {
+        println!(
+            "\x1b[36mlearned more about {} questions from earlier attempts\x1b[0m",
+            solution.learned.len()
+        );
+    }
+    for i in 0..global_data.question_count() {
+        let question = global_data.get_question(i);
+        answered.push((question.useful_id.clone(), question.locale.clone()));
+    }
+
+    global_data.clear_question();
+    Ok(())
+}
+
+/// 将题库文件中的题目导入存储，已存在的同一题目会被覆盖；
+/// 从questions-<课程id>.zip导入的题目不知道课程时归入该课程
+pub async fn import_bank(config: &Config, file: &Path) -> Result<(), Box<dyn Error>> {
+    let mut questions = read_bank(file)?;
+    if let Some(course_id) = course_id_of_bank(file) {
+        questions
+            .iter_mut()
+            .for_each(|q| q.fill_namespace(&course_id, "", ""));
+    }
+    let store = store::open(config).await?;
+    store.save_all(&questions).await?;
+    println!(
+        "imported {} questions from {}",
+        questions.len(),
+        file.display()
+    );
+    Ok(())
+}
+
+/// 将存储中的全部题目导出为题库文件；by_course时file为目录，每个课程写入其中的questions-<课程id>.zip
+pub async fn export_bank(
+    config: &Config,
+    file: &Path,
+    by_course: bool,
+) -> Result<(), Box<dyn Error>> {
+    let store = store::open(config).await?;
+    let questions = store.all().await?;
+    if by_course {
+        let total = questions.len();
+        let courses = split_by_course(questions);
+        std::fs::create_dir_all(file)?;
+        let mut exported = 0;
+        for (course_id, questions) in courses.iter() {
+            let path = file.join(format!("questions-{}.zip", course_id));
+            replace_bank(&path, questions)?;
+            println!(
+                "exported {} questions to {}",
+                questions.len(),
+                path.display()
+            );
+            exported += questions.len();
+        }
+        if exported < total {
+            println!(
+                "\x1b[33m{} questions of unknown course were not exported\x1b[0m",
+                total - exported
+            );
+        }
+        return Ok(());
+    }
+    replace_bank(file, &questions)?;
+    println!(
+        "exported {} questions to {}",
+        questions.len(),
+        file.display()
+    );
+    Ok(())
+}
+
+/// 将题库文件写成供人阅读的学习资料，每个题库文件为一个课程，不需要数据库
+pub fn export_guide(output: &Path, inputs: &[PathBuf]) -> Result<(), Box<dyn Error>> {
+    let mut courses = Vec::new();
+    for input in inputs {
+        for file in bank_files(input)? {
+            let (mut questions, _) = collapse_duplicates(read_bank(&file)?);
+            questions.sort_by(|a, b| (&a.locale, &a.useful_id).cmp(&(&b.locale, &b.useful_id)));
+            courses.push(Course {
+                name: course_of_bank(&file),
+                questions,
+            });
+        }
+    }
+    std::fs::write(output, guide::render(output, &courses)?)?;
+    println!(
+        "wrote {} questions of {} courses to {}",
+        courses.iter().map(|c| c.questions.len()).sum::<usize>(),
+        courses.len(),
+        output.display()
+    );
+    Ok(())
+}
+
+/// 统计存储中题目的掌握情况
+pub async fn stats(config: &Config) -> Result<(), Box<dyn Error>> {
+    let store = store::open(config).await?;
+    let questions = store.all().await?;
+    let count =
+        |f: &dyn Fn(&MemoryStoreQuestion) -> bool| questions.iter().filter(|q| f(q)).count();
+    println!("questions: {}", questions.len());
+    let mut locales = questions
+        .iter()
+        .map(|q| q.locale.clone())
+        .collect::<Vec<String>>();
+    locales.sort();
+    locales.dedup();
+    for locale in locales.iter() {
+        println!("  in {}: {}", locale, count(&|q| &q.locale == locale));
+    }
+    let mut courses = questions
+        .iter()
+        .map(|q| q.course_id.clone())
+        .collect::<Vec<String>>();
+    courses.sort();
+    courses.dedup();
+    for course in courses.iter() {
+        let name = if course.is_empty() {
+            "unknown course"
+        } else {
+            course.as_str()
+        };
+        println!(
+            "  of {}: {} ({} known correct)",
+            name,
+            count(&|q| &q.course_id == course),
+            count(&|q| &q.course_id == course && !q.correct_choices.is_empty())
+        );
+    }
+    println!(
+        "  single answer: {}",
+        count(&|q| q.format == QuestionFormat::MultiChoiceSingleAnswer)
+    );
+    println!(
+        "  multiple answer: {}",
+        count(&|q| q.format == QuestionFormat::MultiChoiceMultipleAnswer)
+    );
+    println!(
+        "  with question text: {}",
+        count(&|q| q.details.question_text.is_some())
+    );
+    println!(
+        "known correct: {}",
+        count(&|q| !q.correct_choices.is_empty())
+    );
+    println!(
+        "  with recorded evidence: {}",
+        count(&|q| !q.correct_choices.is_empty() && !q.evidence.is_empty())
+    );
+    println!(
+        "unknown with eliminations: {}",
+        count(&|q| q.correct_choices.is_empty()
+            && (!q.error_choices.is_empty() || !q.multiple_error_choices.is_empty()))
+    );
+    Ok(())
+}
+
+/// 列出某一题目的了解及推出它们的答题
+pub async fn explain(config: &Config, useful_id: &str, locale: &str) -> Result<(), Box<dyn Error>> {
+    let store = store::open(config).await?;
+    let question = store
+        .find(useful_id, locale)
+        .await?
+        .ok_or_else(|| format!("[{}] in {} is not in the store", useful_id, locale))?;
+    match &question.details.question_text {
+        Some(text) => println!("[{}] in {}: {}", useful_id, locale, text),
+        None => println!("[{}] in {}", useful_id, locale),
+    }
+    let attempts = store.attempts().await?;
+    evidence::explain(&question, &attempts)
+        .iter()
+        .for_each(|line| println!("{}", line));
+    Ok(())
+}
+
+/// 结合存储中的全部答题记录求解，将推出的了解合并进存储
+async fn solve_store(store: &dyn QuestionStore) -> Result<Solution, Box<dyn Error>> {
+    let solution = solver::solve(&store.all().await?, &store.attempts().await?);
+    store.merge_all(&solution.learned).await?;
+    Ok(solution)
+}
+
+/// 结合全部答题记录推出更多的了解并保存
+pub async fn solve(config: &Config) -> Result<(), Box<dyn Error>> {
+    let store = store::open(config).await?;
+    let solution = solve_store(store.as_ref()).await?;
+    for question in solution.learned.iter() {
+        let state = if question.correct_choices.is_empty() {
+            "eliminated more choices"
+        } else {
+            "learned the correct choices"
+        };
+        println!("[{}] in {} {}", question.useful_id, question.locale, state);
+    }
+    for attempt in solution.conflicts.iter() {
+        println!(
+            "\x1b[33mattempt {} contradicts the known answers or earlier attempts, ignored\x1b[0m",
+            attempt
+        );
+    }
+    println!(
+        "learned more about {} questions, ignored {} attempts",
+        solution.learned.len(),
+        solution.conflicts.len()
+    );
+    Ok(())
+}
+
+/// 用当前的推导规则重放存储中的全部答题记录，将得到的题库写入文件，不修改存储
+pub async fn rebuild_bank(config: &Config, file: &Path) -> Result<(), Box<dyn Error>> {
+    let store = store::open(config).await?;
+    let attempts = store.attempts().await?;
+    let mut questions = evidence::rebuild(&store.all().await?, &attempts);
+    for learned in solver::solve(&questions, &attempts).learned {
+        if let Some(question) = questions
+            .iter_mut()
+            .find(|q| q.useful_id == learned.useful_id && q.locale == learned.locale)
+        {
+            *question = learned;
+        }
+    }
+    replace_bank(file, &questions)?;
+    println!(
+        "replayed {} attempts into {} questions ({} with known correct), wrote {}",
+        attempts.len(),
+        questions.len(),
+        questions
+            .iter()
+            .filter(|q| !q.correct_choices.is_empty())
+            .count(),
+        file.display()
+    );
+    Ok(())
+}
+
+/// 检查题库文件能否被正确读取，并报告重复或自相矛盾的题目
+pub fn verify_bank(file: &Path) -> Result<(), Box<dyn Error>> {
+    let mut problems = Vec::new();
+    let mut seen = HashSet::new();
+    let documents = read_documents(file)?;
+    let total = documents.len();
+    for (index, document) in documents.into_iter().enumerate() {
+        let question = match parse_question(document) {
+            Ok(q) => q,
+            Err(error) => {
+                problems.push(format!("document {}: {}", index, error));
+                continue;
+            }
+        };
+        if !seen.insert((question.useful_id.clone(), question.locale.clone())) {
+            problems.push(format!(
+                "document {}: duplicate [{}] in {}",
+                index, question.useful_id, question.locale
+            ));
+        }
+        for problem in verify::verify(&question) {
+            problems.push(format!(
+                "document {}: [{}] {}",
+                index, question.useful_id, problem
+            ));
+        }
+    }
+    problems.iter().for_each(|p| println!("{}", p));
+    println!(
+        "checked {} documents in {}, {} problems",
+        total,
+        file.display(),
+        problems.len()
+    );
+    if problems.is_empty() {
+        Ok(())
+    } else {
+        Err(format!("{} is not a valid bank", file.display()).into())
+    }
+}
+
+/// 合并多个题库文件，报告所有冲突，policy为fail且有冲突时不写入
+pub fn merge_banks(
+    output: &Path,
+    inputs: &[PathBuf],
+    policy: MergePolicy,
+) -> Result<(), Box<dyn Error>> {
+    let banks = inputs
+        .iter()
+        .map(|path| {
+            Ok(Bank {
+                name: path.display().to_string(),
+                modified: std::fs::metadata(path)?.modified()?,
+                questions: read_bank(path)?,
+            })
+        })
+        .collect::<Result<Vec<Bank>, Box<dyn Error>>>()?;
+    let (merged, conflicts) = merge::merge_banks(&banks, policy);
+    conflicts.iter().for_each(|c| println!("conflict: {}", c));
+    match merged {
+        Some(questions) => {
+            replace_bank(output, &questions)?;
+            println!(
+                "merged {} banks into {} questions in {}, {} conflicts",
+                banks.len(),
+                questions.len(),
+                output.display(),
+                conflicts.len()
+            );
+            Ok(())
+        }
+        None => Err(format!("{} conflicts, nothing was written", conflicts.len()).into()),
+    }
+}
+
+/// 比较两个题库文件
+pub fn diff_banks(old: &Path, new: &Path) -> Result<(), Box<dyn Error>> {
+    print_diff(
+        diff::diff_banks(read_bank(old)?, read_bank(new)?),
+        old,
+        &new.display().to_string(),
+    );
+    Ok(())
+}
+
+/// 比较题库文件和存储中的全部题目
+pub async fn diff_store(config: &Config, old: &Path) -> Result<(), Box<dyn Error>> {
+    let store = store::open(config).await?;
+    print_diff(
+        diff::diff_banks(read_bank(old)?, store.all().await?),
+        old,
+        "the store",
+    );
+    Ok(())
+}
+
+fn print_diff(changes: Vec<(Key, Change)>, old: &Path, new: &str) {
+    println!("changes from {} to {}:", old.display(), new);
+    for ((useful_id, locale), change) in changes.iter() {
+        let line = format!("[{}] in {}: {}", useful_id, locale, change);
+        if change.is_regression() {
+            println!("\x1b[31m{}\x1b[0m", line);
+        } else {
+            println!("{}", line);
+        }
+    }
+    let count = |f: &dyn Fn(&Change) -> bool| changes.iter().filter(|(_, c)| f(c)).count();
+    println!(
+        "{} new questions, {} newly known, {} new eliminations, {} regressions",
+        count(&|c| matches!(c, Change::Added)),
+        count(&|c| matches!(c, Change::Learned { .. })),
+        count(&|c| matches!(c, Change::Eliminated { .. })),
+        count(&|c| c.is_regression())
+    );
+}
+
+/// 从"Copy as cURL"复制的命令或HAR文件中提取配置并写入配置文件，source为-时从标准输入读取
+pub fn import_credentials(args: &ConfigArgs, source: &Path) -> Result<(), Box<dyn Error>> {
+    let content = if source == Path::new("-") {
+        let mut content = String::new();
+        std::io::stdin().read_to_string(&mut content)?;
+        content
+    } else {
+        std::fs::read_to_string(source)?
+    };
+    let credentials = extract(&content)?;
+    for (path, keys) in args.save(&credentials.pairs())? {
+        println!("wrote {} to {}", keys.join(", "), path.display());
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::SocketAddr;
+    use std::path::Path;
+
+    use clap::Parser;
+
+    use crate::bank::{read_bank, replace_bank};
+    use crate::command::{answer_iteration, merge_banks};
+    use crate::config::{Config, ConfigArgs};
+    use crate::evidence::Trial;
+    use crate::global::{GlobalData, MemoryStoreQuestion};
+    use crate::merge::MergePolicy;
+    use crate::mock_server;
+    use crate::response::{AnswerChoice, QuestionDetails, QuestionFormat};
+    use crate::schema::SCHEMA_VERSION;
+    use crate::store::{QuestionStore, SqliteStore};
+
+    #[derive(Parser)]
+    struct Cli {
+        #[command(flatten)]
+        args: ConfigArgs,
+    }
+
+    /// 启动只有bank中题目的模拟服务器，返回连接它的配置和dir中的sqlite存储
+    async fn connect(
+        bank: Vec<MemoryStoreQuestion>,
+        question_count: usize,
+        dir: &Path,
+    ) -> (ConfigArgs, Config, SqliteStore) {
+        let (addr, server) =
+            mock_server::bind(bank, SocketAddr::from(([127, 0, 0, 1], 0)), question_count).unwrap();
+        tokio::spawn(server);
+
+        std::fs::create_dir_all(dir).unwrap();
+        let path = dir.join(".env");
+        std::fs::write(
+            &path,
+            format!(
+                "QUIZ_ID=quiz-1\nCOOKIE=\"JSESSIONID=1; PSA_STUD_CPNT_ID=1; PSA_STUD_CPNT_MOD_ID=2\"\n\
+                 CSRF_TOKEN=token\nAPI_SCHEME=http\nAPI_HOST={}\nAPI_PREFIX=/\n",
+                addr
+            ),
+        )
+        .unwrap();
+        let args = Cli::parse_from(["wbl-robot", "--config", path.to_str().unwrap()]).args;
+        let config = Config::load(&args, true).unwrap();
+        let store = SqliteStore::open(&dir.join("questions.sqlite")).unwrap();
+        store.prepare().await.unwrap();
+        (args, config, store)
+    }
+
+    fn question(useful_id: &str) -> MemoryStoreQuestion {
+        MemoryStoreQuestion {
+            useful_id: useful_id.to_string(),
+            temp_id: String::new(),
+            locale: String::from("English"),
+            course_id: String::new(),
+            quiz_id: String::new(),
+            quiz_sys_guid: String::new(),
+            format: QuestionFormat::MultiChoiceSingleAnswer,
+            details: QuestionDetails::default(),
+            choices: vec![
+                AnswerChoice::from_mock("1"),
+                AnswerChoice::from_mock("2"),
+                AnswerChoice::from_mock("3"),
+            ],
+            correct_choices: vec![AnswerChoice::from_mock("3")],
+            error_choices: Vec::new(),
+            multiple_error_choices: Vec::new(),
+            chosen_choices: Vec::new(),
+            evidence: Vec::new(),
+            tried_choices: Vec::new(),
+            schema_version: SCHEMA_VERSION,
+        }
+    }
+
+    /// 有题目被有意答错时不会满分，全部探测都答对的答题要靠求解记下正确答案
+    #[tokio::test]
+    async fn all_correct_probes_test() {
+        let bank = ["q1", "q2", "q3"].map(question).to_vec();
+        let dir = std::env::temp_dir().join(format!("wbl-robot-answer-{}", std::process::id()));
+        let (args, mut config, store) = connect(bank.clone(), 3, &dir).await;
+
+        // 每题都排除了1、试过了2，探测时选中正确的3；第三题再探测不划算，用已知错误的1作答
+        let unknown = bank
+            .iter()
+            .map(|q| {
+                let mut q = q.clone();
+                q.correct_choices.clear();
+                q.error_choices = vec![AnswerChoice::from_mock("1")];
+                q.tried_choices = vec![Trial::new(&AnswerChoice::from_mock("2"), "earlier")];
+                q
+            })
+            .collect::<Vec<_>>();
+        store.save_all(&unknown).await.unwrap();
+
+        let mut global_data = GlobalData::from_config(&config);
+        let mut answered = Vec::new();
+        answer_iteration(
+            &args,
+            &mut config,
+            &store,
+            &reqwest::Client::new(),
+            &mut global_data,
+            &mut answered,
+        )
+        .await
+        .unwrap();
+
+        let attempts = store.attempts().await.unwrap();
+        assert_eq!(attempts.len(), 1);
+        assert_eq!(attempts[0].questions_correct, 2);
+        assert_ne!(attempts[0].score, 100);
+        let learned = store
+            .all()
+            .await
+            .unwrap()
+            .into_iter()
+            .filter(|q| {
+                q.correct_choices.len() == 1
+                    && q.correct_choices[0].same_choice(&AnswerChoice::from_mock("3"))
+            })
+            .count();
+        assert_eq!(learned, 2);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    /// 只有一题的答题直接提交第一题，没有题目的答题返回错误
+    #[tokio::test]
+    async fn question_count_test() {
+        let dir = std::env::temp_dir().join(format!("wbl-robot-count-{}", std::process::id()));
+        let (args, mut config, store) = connect(vec![question("q1")], 1, &dir).await;
+        let mut global_data = GlobalData::from_config(&config);
+        let mut answered = Vec::new();
+        answer_iteration(
+            &args,
+            &mut config,
+            &store,
+            &reqwest::Client::new(),
+            &mut global_data,
+            &mut answered,
+        )
+        .await
+        .unwrap();
+        assert_eq!(answered.len(), 1);
+        assert_eq!(store.attempts().await.unwrap()[0].answers.len(), 1);
+
+        let (args, mut config, store) = connect(vec![question("q1")], 0, &dir).await;
+        let mut global_data = GlobalData::from_config(&config);
+        let error = answer_iteration(
+            &args,
+            &mut config,
+            &store,
+            &reqwest::Client::new(),
+            &mut global_data,
+            &mut answered,
+        )
+        .await
+        .unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "the assessment iteration has no questions"
+        );
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    /// 合并的结果可以写回其中一个输入，写完后不留下临时文件
+    #[test]
+    fn merge_into_input_test() {
+        let dir = std::env::temp_dir().join(format!("wbl-robot-merge-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let first = dir.join("questions-1.zip");
+        let second = dir.join("other.json");
+        replace_bank(&first, &[question("q1")]).unwrap();
+        replace_bank(&second, &[question("q2")]).unwrap();
+
+        merge_banks(&first, &[first.clone(), second], MergePolicy::Evidence).unwrap();
+        let mut merged = read_bank(&first)
+            .unwrap()
+            .into_iter()
+            .map(|q| q.useful_id)
+            .collect::<Vec<_>>();
+        merged.sort();
+        assert_eq!(merged, ["q1", "q2"]);
+        let names = std::fs::read_dir(&dir)
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name().to_string_lossy().to_string())
+            .collect::<Vec<_>>();
+        assert!(names.iter().all(|name| !name.starts_with(".tmp-")));
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
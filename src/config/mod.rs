@@ -0,0 +1,821 @@
+use std::collections::HashMap;
+use std::env;
+use std::error::Error;
+use std::fmt;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+
+use clap::Args;
+use wbl_robot::{cookies, load_env_file, parse_env, update_env_file, Secret};
+
+/// 未指定--config时默认读取的配置文件
+const DEFAULT_CONFIG_FILE: &str = ".env";
+
+/// 未配置LOCALE时答题使用的语言
+pub const DEFAULT_LOCALE: &str = "English";
+
+/// 指定配置文件路径的环境变量
+const CONFIG_FILE_ENV: &str = "WBL_ROBOT_CONFIG";
+
+/// 指定凭据文件路径的环境变量
+const CREDENTIALS_FILE_ENV: &str = "WBL_ROBOT_CREDENTIALS";
+
+/// 保存在凭据文件中而不是配置文件中的配置项
+const SECRET_KEYS: [&str; 2] = ["COOKIE", "CSRF_TOKEN"];
+
+/// 写入了配置项的文件，以及写入了哪些配置项
+type Written = Vec<(PathBuf, Vec<String>)>;
+
+/// 已确定的配置项，键名到值和来源
+type Values<'a> = HashMap<&'a str, (String, Source)>;
+
+/// 配置项未配置时的取值方式
+enum Fallback {
+    /// 必填
+    Required,
+    /// 使用默认值
+    Value(&'static str),
+    /// 从COOKIE中同名的cookie读取
+    Cookie,
+    /// 根据前面已确定的配置项推导
+    Derived(fn(&Values) -> Option<String>),
+    /// 可以不配置，不配置时为空
+    Optional,
+}
+
+/// 一个配置项：.env和环境变量中的键名，对应的命令行参数，未配置时的取值方式，
+/// 以及是否只有答题时才需要
+struct Key {
+    name: &'static str,
+    flag: &'static str,
+    fallback: Fallback,
+    quiz: bool,
+}
+
+const KEYS: [Key; 18] = [
+    Key {
+        name: "QUIZ_ID",
+        flag: "--quiz-id",
+        fallback: Fallback::Required,
+        quiz: true,
+    },
+    Key {
+        name: "COOKIE",
+        flag: "--cookie",
+        fallback: Fallback::Required,
+        quiz: true,
+    },
+    Key {
+        name: "CSRF_TOKEN",
+        flag: "--csrf-token",
+        fallback: Fallback::Required,
+        quiz: true,
+    },
+    Key {
+        name: "PSA_STUD_CPNT_ID",
+        flag: "--student-component-id",
+        fallback: Fallback::Cookie,
+        quiz: true,
+    },
+    Key {
+        name: "PSA_STUD_CPNT_MOD_ID",
+        flag: "--module-id",
+        fallback: Fallback::Cookie,
+        quiz: true,
+    },
+    Key {
+        name: "LOCALE",
+        flag: "--locale",
+        fallback: Fallback::Value(DEFAULT_LOCALE),
+        quiz: false,
+    },
+    Key {
+        name: "API_SCHEME",
+        flag: "--api-scheme",
+        fallback: Fallback::Value("https"),
+        quiz: false,
+    },
+    Key {
+        name: "API_TENANT",
+        flag: "--api-tenant",
+        fallback: Fallback::Value("eygsl"),
+        quiz: false,
+    },
+    Key {
+        name: "API_HOST",
+        flag: "--api-host",
+        fallback: Fallback::Derived(tenant_host),
+        quiz: false,
+    },
+    Key {
+        name: "API_PREFIX",
+        flag: "--api-prefix",
+        fallback: Fallback::Value("/learning/user/api/v1"),
+        quiz: false,
+    },
+    Key {
+        name: "COURSE_ID",
+        flag: "--course-id",
+        fallback: Fallback::Optional,
+        quiz: false,
+    },
+    Key {
+        name: "BANK",
+        flag: "--bank",
+        fallback: Fallback::Optional,
+        quiz: false,
+    },
+    Key {
+        name: "STORE",
+        flag: "--store",
+        fallback: Fallback::Value("mongodb"),
+        quiz: false,
+    },
+    Key {
+        name: "STORE_PATH",
+        flag: "--store-path",
+        fallback: Fallback::Derived(store_path),
+        quiz: false,
+    },
+    Key {
+        name: "QUARANTINE_PATH",
+        flag: "--quarantine-path",
+        fallback: Fallback::Value("quarantine.json"),
+        quiz: false,
+    },
+    Key {
+        name: "MONGO_URI",
+        flag: "--mongo-uri",
+        fallback: Fallback::Value("mongodb://localhost:27017"),
+        quiz: false,
+    },
+    Key {
+        name: "MONGO_DATABASE",
+        flag: "--mongo-database",
+        fallback: Fallback::Value("wbl_robot"),
+        quiz: false,
+    },
+    Key {
+        name: "MONGO_COLLECTION",
+        flag: "--mongo-collection",
+        fallback: Fallback::Value("questions"),
+        quiz: false,
+    },
+];
+
+/// 未配置API_HOST时，使用租户在plateau上的域名
+fn tenant_host(values: &Values) -> Option<String> {
+    let (tenant, _) = values.get("API_TENANT")?;
+    Some(format!("{}.plateau.com", tenant))
+}
+
+/// 未配置STORE_PATH时，sqlite和json的存储使用当前目录下的默认文件，mongodb不使用该项
+fn store_path(values: &Values) -> Option<String> {
+    let (store, _) = values.get("STORE")?;
+    let path = match store.as_str() {
+        "sqlite" => "questions.sqlite",
+        _ => "questions.json",
+    };
+    Some(path.to_string())
+}
+
+/// 命令行中可以覆盖配置的参数，所有子命令通用
+#[derive(Args, Debug, Default)]
+pub struct ConfigArgs {
+    /// Config file to read instead of .env
+    #[arg(long, global = true, value_name = "PATH")]
+    config: Option<String>,
+    /// File with COOKIE and CSRF_TOKEN in .env format, kept outside the repository and
+    /// only readable by its owner (chmod 600)
+    #[arg(long, global = true, value_name = "PATH")]
+    credentials_file: Option<String>,
+    /// Read COOKIE and CSRF_TOKEN in .env format from stdin, e.g. from a password manager
+    #[arg(long, global = true, conflicts_with = "credentials_file")]
+    credentials_stdin: bool,
+    /// Overrides QUIZ_ID
+    #[arg(long, global = true)]
+    quiz_id: Option<String>,
+    /// Overrides COOKIE
+    #[arg(long, global = true)]
+    cookie: Option<String>,
+    /// Overrides CSRF_TOKEN
+    #[arg(long, global = true)]
+    csrf_token: Option<String>,
+    /// Overrides PSA_STUD_CPNT_ID (the studentComponentID of the iteration url)
+    #[arg(long, global = true)]
+    student_component_id: Option<String>,
+    /// Overrides PSA_STUD_CPNT_MOD_ID (the moduleID of the iteration url)
+    #[arg(long, global = true)]
+    module_id: Option<String>,
+    /// Overrides LOCALE, the language the quiz is taken in, e.g. English
+    #[arg(long, global = true)]
+    locale: Option<String>,
+    /// Overrides API_SCHEME, use http for a local mock server
+    #[arg(long, global = true)]
+    api_scheme: Option<String>,
+    /// Overrides API_TENANT
+    #[arg(long, global = true)]
+    api_tenant: Option<String>,
+    /// Overrides API_HOST, e.g. 127.0.0.1:8080
+    #[arg(long, global = true)]
+    api_host: Option<String>,
+    /// Overrides API_PREFIX
+    #[arg(long, global = true)]
+    api_prefix: Option<String>,
+    /// Overrides COURSE_ID, e.g. HRC8045921, taken from the PSA_CPNT_ID cookie when not set
+    #[arg(long, global = true)]
+    course_id: Option<String>,
+    /// Overrides BANK, a bank file or a directory of questions-<course id>.zip banks that
+    /// run reads at startup and writes back at shutdown
+    #[arg(long = "bank", global = true, value_name = "PATH")]
+    bank_path: Option<String>,
+    /// Overrides STORE, where questions are kept: mongodb, sqlite or json
+    #[arg(long, global = true)]
+    store: Option<String>,
+    /// Overrides STORE_PATH, the database file of sqlite or the bank file of json
+    #[arg(long, global = true, value_name = "PATH")]
+    store_path: Option<String>,
+    /// Overrides QUARANTINE_PATH, the bank file inconsistent questions are moved to
+    #[arg(long, global = true, value_name = "PATH")]
+    quarantine_path: Option<String>,
+    /// Overrides MONGO_URI
+    #[arg(long, global = true)]
+    mongo_uri: Option<String>,
+    /// Overrides MONGO_DATABASE
+    #[arg(long, global = true)]
+    mongo_database: Option<String>,
+    /// Overrides MONGO_COLLECTION
+    #[arg(long, global = true)]
+    mongo_collection: Option<String>,
+}
+
+impl ConfigArgs {
+    /// 配置文件的路径，以及是否是通过--config或环境变量明确指定的
+    pub fn config_path(&self) -> (PathBuf, bool) {
+        match self
+            .config
+            .clone()
+            .or_else(|| env::var(CONFIG_FILE_ENV).ok())
+        {
+            Some(path) => (PathBuf::from(path), true),
+            None => (PathBuf::from(DEFAULT_CONFIG_FILE), false),
+        }
+    }
+
+    /// 凭据文件的路径，通过--credentials-file或环境变量指定
+    pub fn credentials_path(&self) -> Option<PathBuf> {
+        self.credentials_file
+            .clone()
+            .or_else(|| env::var(CREDENTIALS_FILE_ENV).ok())
+            .map(PathBuf::from)
+    }
+
+    /// 凭据是否从标准输入读取
+    pub fn credentials_stdin(&self) -> bool {
+        self.credentials_stdin
+    }
+
+    /// 将配置项写入文件：指定了凭据文件时COOKIE和CSRF_TOKEN写入凭据文件，其余写入配置文件，
+    /// 返回每个文件写入了哪些配置项
+    pub fn save(&self, pairs: &[(&str, &str)]) -> Result<Written, Box<dyn Error>> {
+        if self.credentials_stdin {
+            return Err(
+                "credentials are read from stdin, use --credentials-file to save them".into(),
+            );
+        }
+        let (config_path, _) = self.config_path();
+        let credentials_path = self.credentials_path();
+        let (secrets, others): (Vec<_>, Vec<_>) = pairs
+            .iter()
+            .partition(|(key, _)| credentials_path.is_some() && SECRET_KEYS.contains(key));
+        let mut written = Vec::new();
+        if let Some(path) = credentials_path.filter(|_| !secrets.is_empty()) {
+            restrict_permissions(&path)?;
+            update_env_file(&path, &secrets)?;
+            written.push((
+                path,
+                secrets.iter().map(|(key, _)| key.to_string()).collect(),
+            ));
+        }
+        if !others.is_empty() {
+            update_env_file(&config_path, &others)?;
+            written.push((
+                config_path,
+                others.iter().map(|(key, _)| key.to_string()).collect(),
+            ));
+        }
+        Ok(written)
+    }
+
+    /// 命令行中给出的配置项，以配置键名为key
+    fn flags(&self) -> HashMap<String, String> {
+        [
+            ("QUIZ_ID", &self.quiz_id),
+            ("COOKIE", &self.cookie),
+            ("CSRF_TOKEN", &self.csrf_token),
+            ("PSA_STUD_CPNT_ID", &self.student_component_id),
+            ("PSA_STUD_CPNT_MOD_ID", &self.module_id),
+            ("LOCALE", &self.locale),
+            ("API_SCHEME", &self.api_scheme),
+            ("API_TENANT", &self.api_tenant),
+            ("API_HOST", &self.api_host),
+            ("API_PREFIX", &self.api_prefix),
+            ("COURSE_ID", &self.course_id),
+            ("BANK", &self.bank_path),
+            ("STORE", &self.store),
+            ("STORE_PATH", &self.store_path),
+            ("QUARANTINE_PATH", &self.quarantine_path),
+            ("MONGO_URI", &self.mongo_uri),
+            ("MONGO_DATABASE", &self.mongo_database),
+            ("MONGO_COLLECTION", &self.mongo_collection),
+        ]
+        .into_iter()
+        .filter_map(|(name, value)| value.clone().map(|v| (name.to_string(), v)))
+        .collect()
+    }
+}
+
+/// 运行所需的全部配置，不需要答题的子命令中答题相关的项可能为空
+#[derive(Debug, Clone)]
+pub struct Config {
+    pub quiz_id: String,
+    pub cookie: Secret,
+    pub csrf_token: Secret,
+    pub student_component_id: String,
+    pub module_id: String,
+    /// 答题使用的语言
+    pub locale: String,
+    /// quiz接口的地址，如https://eygsl.plateau.com/learning/user/api/v1
+    pub api_base: String,
+    /// 课程id，如HRC8045921，未知时为空
+    pub course_id: String,
+    /// 答题时读取和写回的题库文件或题库目录，未配置时为空
+    pub bank: String,
+    /// 题目保存在哪里：mongodb、sqlite或json
+    pub store: String,
+    /// sqlite的数据库文件或json的题库文件
+    pub store_path: String,
+    /// 自相矛盾的题目被隔离到的题库文件
+    pub quarantine_path: String,
+    pub mongo_uri: String,
+    pub mongo_database: String,
+    pub mongo_collection: String,
+}
+
+/// 配置中所有缺失或格式错误的项，一次性报告
+#[derive(Debug)]
+pub struct ConfigError {
+    problems: Vec<String>,
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "invalid configuration:")?;
+        for problem in &self.problems {
+            writeln!(f, "  - {}", problem)?;
+        }
+        Ok(())
+    }
+}
+
+impl Error for ConfigError {}
+
+/// 某个配置值的来源，用于错误提示
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Source {
+    Default,
+    File,
+    Credentials,
+    Env,
+    Flag,
+}
+
+impl fmt::Display for Source {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Source::Default => "default",
+            Source::File => "config file",
+            Source::Credentials => "credentials",
+            Source::Env => "environment",
+            Source::Flag => "command line",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+impl Config {
+    /// 按 默认值 < 配置文件 < 凭据文件或标准输入 < 环境变量 < 命令行参数 的优先级加载配置，
+    /// require_quiz为false时不检查只有答题才需要的配置项
+    pub fn load(args: &ConfigArgs, require_quiz: bool) -> Result<Config, ConfigError> {
+        let mut problems = Vec::new();
+
+        let (path, explicit) = args.config_path();
+        let file = match load_env_file(&path) {
+            Ok(vars) => vars,
+            Err(error) => {
+                // 默认的.env不存在时直接跳过，容器和脚本中通常只用环境变量
+                if explicit || path.exists() {
+                    problems.push(format!(
+                        "unable to read config file {}: {}",
+                        path.display(),
+                        error
+                    ));
+                }
+                HashMap::new()
+            }
+        };
+
+        let credentials = if args.credentials_stdin {
+            match parse_env(stdin_credentials()) {
+                Ok(vars) => vars,
+                Err(error) => {
+                    problems.push(format!("unable to read credentials from stdin: {}", error));
+                    HashMap::new()
+                }
+            }
+        } else if let Some(path) = args.credentials_path() {
+            match check_permissions(&path).and_then(|_| Ok(load_env_file(&path)?)) {
+                Ok(vars) => vars,
+                Err(error) => {
+                    problems.push(format!(
+                        "unable to read credentials file {}: {}",
+                        path.display(),
+                        error
+                    ));
+                    HashMap::new()
+                }
+            }
+        } else {
+            HashMap::new()
+        };
+
+        let vars = KEYS
+            .iter()
+            .filter_map(|key| env::var(key.name).ok().map(|v| (key.name.to_string(), v)))
+            .collect::<HashMap<String, String>>();
+
+        Config::from_layers(
+            &file,
+            &credentials,
+            &vars,
+            &args.flags(),
+            problems,
+            require_quiz,
+        )
+    }
+
+    fn from_layers(
+        file: &HashMap<String, String>,
+        credentials: &HashMap<String, String>,
+        vars: &HashMap<String, String>,
+        flags: &HashMap<String, String>,
+        mut problems: Vec<String>,
+        require_quiz: bool,
+    ) -> Result<Config, ConfigError> {
+        let mut values: Values = HashMap::new();
+        for key in KEYS.iter() {
+            let default = match key.fallback {
+                Fallback::Value(value) => Some(value.to_string()),
+                _ => None,
+            };
+            let layers = [
+                (default, Source::Default),
+                (file.get(key.name).cloned(), Source::File),
+                (credentials.get(key.name).cloned(), Source::Credentials),
+                (vars.get(key.name).cloned(), Source::Env),
+                (flags.get(key.name).cloned(), Source::Flag),
+            ];
+            let value = layers
+                .into_iter()
+                .rev()
+                .find_map(|(value, source)| value.map(|v| (v.trim().to_string(), source)));
+            let fallback = || match key.fallback {
+                Fallback::Cookie => {
+                    let (cookie, source) = values.get("COOKIE")?;
+                    let value = cookies(cookie).remove(key.name)?;
+                    Some((value, *source))
+                }
+                Fallback::Derived(derive) => derive(&values).map(|v| (v, Source::Default)),
+                _ => None,
+            };
+            let value = match value {
+                Some((v, source)) if !v.is_empty() => Some((v, source)),
+                _ => fallback(),
+            };
+            match value {
+                Some((v, source)) if !v.is_empty() => {
+                    values.insert(key.name, (v, source));
+                }
+                _ if matches!(key.fallback, Fallback::Optional) => {
+                    values.insert(key.name, (String::new(), Source::Default));
+                }
+                _ if key.quiz && !require_quiz => {
+                    values.insert(key.name, (String::new(), Source::Default));
+                }
+                _ if matches!(key.fallback, Fallback::Cookie) => problems.push(format!(
+                    "missing [{}] (set it in {}, the {} environment variable, {} or as a cookie in COOKIE)",
+                    key.name, DEFAULT_CONFIG_FILE, key.name, key.flag
+                )),
+                _ => problems.push(format!(
+                    "missing [{}] (set it in {}, the {} environment variable or {})",
+                    key.name, DEFAULT_CONFIG_FILE, key.name, key.flag
+                )),
+            }
+        }
+
+        if let Some((quiz_id, source)) = values.get("QUIZ_ID") {
+            if quiz_id.contains(|c: char| c == '/' || c == '?' || c.is_whitespace()) {
+                problems.push(format!(
+                    "malformed [QUIZ_ID] from {}: expected the id after quiz/ in the request url",
+                    source
+                ));
+            }
+        }
+        if let Some((scheme, source)) = values.get("API_SCHEME") {
+            if scheme != "http" && scheme != "https" {
+                problems.push(format!(
+                    "malformed [API_SCHEME] from {}: expected http or https",
+                    source
+                ));
+            }
+        }
+        if let Some((host, source)) = values.get("API_HOST") {
+            if host.contains(|c: char| c == '/' || c.is_whitespace()) {
+                problems.push(format!(
+                    "malformed [API_HOST] from {}: expected a host name with an optional port",
+                    source
+                ));
+            }
+        }
+        if let Some((store, source)) = values.get("STORE") {
+            if !["mongodb", "sqlite", "json"].contains(&store.as_str()) {
+                problems.push(format!(
+                    "malformed [STORE] from {}: expected mongodb, sqlite or json",
+                    source
+                ));
+            }
+        }
+        if let Some((uri, source)) = values.get("MONGO_URI") {
+            if !uri.starts_with("mongodb://") && !uri.starts_with("mongodb+srv://") {
+                problems.push(format!(
+                    "malformed [MONGO_URI] from {}: expected a mongodb:// connection string",
+                    source
+                ));
+            }
+        }
+
+        if !problems.is_empty() {
+            return Err(ConfigError { problems });
+        }
+        let mut take = |name: &str| values.remove(name).expect("validated above").0;
+        let api_scheme = take("API_SCHEME");
+        let api_host = take("API_HOST");
+        let api_prefix = take("API_PREFIX");
+        let api_prefix = api_prefix.trim_matches('/');
+        let api_base = if api_prefix.is_empty() {
+            format!("{}://{}", api_scheme, api_host)
+        } else {
+            format!("{}://{}/{}", api_scheme, api_host, api_prefix)
+        };
+        let cookie = take("COOKIE");
+        let course_id = match take("COURSE_ID") {
+            id if id.is_empty() => cookies(&cookie)
+                .remove("PSA_CPNT_ID")
+                .map(|component| course_of(&component))
+                .unwrap_or_default(),
+            id => id,
+        };
+        Ok(Config {
+            quiz_id: take("QUIZ_ID"),
+            course_id,
+            bank: take("BANK"),
+            cookie: cookie.into(),
+            csrf_token: take("CSRF_TOKEN").into(),
+            student_component_id: take("PSA_STUD_CPNT_ID"),
+            module_id: take("PSA_STUD_CPNT_MOD_ID"),
+            locale: take("LOCALE"),
+            api_base,
+            store: take("STORE"),
+            store_path: take("STORE_PATH"),
+            quarantine_path: take("QUARANTINE_PATH"),
+            mongo_uri: take("MONGO_URI"),
+            mongo_database: take("MONGO_DATABASE"),
+            mongo_collection: take("MONGO_COLLECTION"),
+        })
+    }
+}
+
+/// 从PSA_CPNT_ID cookie中取出课程id，如GLOBALASNHRC6280897中的HRC6280897，与题库文件名一致
+fn course_of(component: &str) -> String {
+    match component.find("HRC") {
+        Some(index) => component[index..].to_string(),
+        None => component.to_string(),
+    }
+}
+
+/// 标准输入只能读取一次，会话过期后重新加载配置时使用第一次读到的内容
+fn stdin_credentials() -> &'static str {
+    static CONTENT: OnceLock<String> = OnceLock::new();
+    CONTENT.get_or_init(|| {
+        let mut content = String::new();
+        // 读取失败时按没有凭据处理，之后会报告缺少COOKIE和CSRF_TOKEN
+        let _ = std::io::stdin().read_to_string(&mut content);
+        content
+    })
+}
+
+/// 凭据文件不能被其他用户读取
+#[cfg(unix)]
+fn check_permissions(path: &Path) -> Result<(), Box<dyn Error>> {
+    use std::os::unix::fs::PermissionsExt;
+    let mode = std::fs::metadata(path)?.permissions().mode();
+    if mode & 0o077 != 0 {
+        return Err(format!(
+            "it can be read by other users, run chmod 600 {}",
+            path.display()
+        )
+        .into());
+    }
+    Ok(())
+}
+
+#[cfg(not(unix))]
+fn check_permissions(_path: &Path) -> Result<(), Box<dyn Error>> {
+    Ok(())
+}
+
+/// 将凭据文件的权限设为只有所有者可以读写，文件不存在时先以该权限创建，写入前就不能被其他用户读取
+#[cfg(unix)]
+fn restrict_permissions(path: &Path) -> Result<(), Box<dyn Error>> {
+    use std::os::unix::fs::{OpenOptionsExt, PermissionsExt};
+    std::fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .mode(0o600)
+        .open(path)?;
+    std::fs::set_permissions(path, std::fs::Permissions::from_mode(0o600))?;
+    Ok(())
+}
+
+#[cfg(not(unix))]
+fn restrict_permissions(_path: &Path) -> Result<(), Box<dyn Error>> {
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use crate::config::Config;
+
+    const COOKIE: &str = "JSESSIONID=1; PSA_STUD_CPNT_ID=35792014; PSA_STUD_CPNT_MOD_ID=653819";
+
+    fn layer(pairs: &[(&str, &str)]) -> HashMap<String, String> {
+        pairs
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn precedence_test() {
+        let file = layer(&[
+            ("QUIZ_ID", "from-file"),
+            ("COOKIE", COOKIE),
+            ("CSRF_TOKEN", "file-token"),
+        ]);
+        let vars = layer(&[("QUIZ_ID", "from-env"), ("CSRF_TOKEN", "env-token")]);
+        let flags = layer(&[("QUIZ_ID", "from-flag")]);
+        let config =
+            Config::from_layers(&file, &HashMap::new(), &vars, &flags, Vec::new(), true).unwrap();
+        assert_eq!(config.quiz_id, "from-flag");
+        assert_eq!(config.csrf_token.expose(), "env-token");
+        assert_eq!(config.cookie.expose(), COOKIE);
+        assert_eq!(config.student_component_id, "35792014");
+        assert_eq!(config.module_id, "653819");
+        assert_eq!(config.store, "mongodb");
+        assert_eq!(config.course_id, "");
+        assert_eq!(config.bank, "");
+        assert_eq!(config.mongo_uri, "mongodb://localhost:27017");
+        assert_eq!(config.mongo_database, "wbl_robot");
+        assert_eq!(config.locale, "English");
+        assert_eq!(
+            config.api_base,
+            "https://eygsl.plateau.com/learning/user/api/v1"
+        );
+    }
+
+    #[test]
+    fn credentials_layer_test() {
+        let file = layer(&[
+            ("QUIZ_ID", "id"),
+            ("COOKIE", "JSESSIONID=stale"),
+            ("CSRF_TOKEN", "stale"),
+            ("PSA_STUD_CPNT_ID", "1"),
+            ("PSA_STUD_CPNT_MOD_ID", "2"),
+        ]);
+        let credentials = layer(&[("COOKIE", COOKIE), ("CSRF_TOKEN", "fresh")]);
+        let config = Config::from_layers(
+            &file,
+            &credentials,
+            &HashMap::new(),
+            &HashMap::new(),
+            Vec::new(),
+            true,
+        )
+        .unwrap();
+        assert_eq!(config.cookie.expose(), COOKIE);
+        assert_eq!(config.csrf_token.expose(), "fresh");
+        assert!(!format!("{:?}", config).contains("fresh"));
+    }
+
+    #[test]
+    fn api_base_test() {
+        let file = layer(&[("QUIZ_ID", "id"), ("COOKIE", COOKIE), ("CSRF_TOKEN", "t")]);
+        let vars = layer(&[("API_TENANT", "other")]);
+        let config = Config::from_layers(
+            &file,
+            &HashMap::new(),
+            &vars,
+            &HashMap::new(),
+            Vec::new(),
+            true,
+        )
+        .unwrap();
+        assert_eq!(
+            config.api_base,
+            "https://other.plateau.com/learning/user/api/v1"
+        );
+        let flags = layer(&[
+            ("API_SCHEME", "http"),
+            ("API_HOST", "127.0.0.1:8080"),
+            ("API_PREFIX", "/api/"),
+        ]);
+        let config =
+            Config::from_layers(&file, &HashMap::new(), &vars, &flags, Vec::new(), true).unwrap();
+        assert_eq!(config.api_base, "http://127.0.0.1:8080/api");
+    }
+
+    #[test]
+    fn reports_every_problem_test() {
+        let file = layer(&[("COOKIE", "JSESSIONID=1"), ("MONGO_URI", "localhost")]);
+        let error = Config::from_layers(
+            &file,
+            &HashMap::new(),
+            &HashMap::new(),
+            &HashMap::new(),
+            Vec::new(),
+            true,
+        )
+        .unwrap_err();
+        assert_eq!(error.problems.len(), 5);
+        let message = error.to_string();
+        assert!(message.contains("[QUIZ_ID]"));
+        assert!(message.contains("[CSRF_TOKEN]"));
+        assert!(message.contains("PSA_STUD_CPNT_ID"));
+        assert!(message.contains("PSA_STUD_CPNT_MOD_ID"));
+        assert!(message.contains("[MONGO_URI]"));
+    }
+
+    #[test]
+    fn quiz_keys_optional_test() {
+        let file = layer(&[("COOKIE", "JSESSIONID=1")]);
+        let config = Config::from_layers(
+            &file,
+            &HashMap::new(),
+            &HashMap::new(),
+            &HashMap::new(),
+            Vec::new(),
+            false,
+        )
+        .unwrap();
+        assert_eq!(config.quiz_id, "");
+        assert_eq!(config.mongo_collection, "questions");
+    }
+
+    #[test]
+    fn course_id_test() {
+        let file = layer(&[("COOKIE", "JSESSIONID=1; PSA_CPNT_ID=GLOBALASNHRC6280897")]);
+        let none = HashMap::new();
+        let config = Config::from_layers(&file, &none, &none, &none, Vec::new(), false).unwrap();
+        assert_eq!(config.course_id, "HRC6280897");
+        let flags = layer(&[("COURSE_ID", "HRC8045921")]);
+        let config = Config::from_layers(&file, &none, &none, &flags, Vec::new(), false).unwrap();
+        assert_eq!(config.course_id, "HRC8045921");
+    }
+
+    #[test]
+    fn store_path_test() {
+        let file = layer(&[("STORE", "sqlite")]);
+        let none = HashMap::new();
+        let config = Config::from_layers(&file, &none, &none, &none, Vec::new(), false).unwrap();
+        assert_eq!(config.store_path, "questions.sqlite");
+        let flags = layer(&[("STORE", "json"), ("STORE_PATH", "bank/a.zip")]);
+        let config = Config::from_layers(&file, &none, &none, &flags, Vec::new(), false).unwrap();
+        assert_eq!(config.store, "json");
+        assert_eq!(config.store_path, "bank/a.zip");
+        let flags = layer(&[("STORE", "redis")]);
+        assert!(Config::from_layers(&file, &none, &none, &flags, Vec::new(), false).is_err());
+    }
+}
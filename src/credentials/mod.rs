@@ -0,0 +1,348 @@
+use std::error::Error;
+
+use reqwest::Url;
+use serde_json::Value;
+
+use wbl_robot::{cookies, Secret};
+
+/// 从浏览器抓到的一条quiz请求中得到的配置
+#[derive(Debug, PartialEq)]
+pub struct Credentials {
+    pub quiz_id: String,
+    pub cookie: Secret,
+    pub csrf_token: Secret,
+    pub student_component_id: Option<String>,
+    pub module_id: Option<String>,
+    pub api_scheme: String,
+    pub api_host: String,
+    pub api_prefix: String,
+}
+
+impl Credentials {
+    /// 需要写入配置的键值对
+    pub fn pairs(&self) -> Vec<(&str, &str)> {
+        let mut pairs = vec![
+            ("QUIZ_ID", self.quiz_id.as_str()),
+            ("COOKIE", self.cookie.expose()),
+            ("CSRF_TOKEN", self.csrf_token.expose()),
+            ("API_SCHEME", self.api_scheme.as_str()),
+            ("API_HOST", self.api_host.as_str()),
+            ("API_PREFIX", self.api_prefix.as_str()),
+        ];
+        if let Some(id) = &self.student_component_id {
+            pairs.push(("PSA_STUD_CPNT_ID", id.as_str()));
+        }
+        if let Some(id) = &self.module_id {
+            pairs.push(("PSA_STUD_CPNT_MOD_ID", id.as_str()));
+        }
+        pairs
+    }
+}
+
+/// 根据内容判断是HAR文件还是"Copy as cURL"复制的命令
+pub fn extract(content: &str) -> Result<Credentials, Box<dyn Error>> {
+    match serde_json::from_str::<Value>(content) {
+        Ok(har) => from_har(&har),
+        Err(_) => from_curl(content),
+    }
+}
+
+/// 从浏览器"Copy as cURL"（bash或cmd格式）复制的命令中提取配置
+pub fn from_curl(command: &str) -> Result<Credentials, Box<dyn Error>> {
+    let tokens = shell_words(command);
+    let mut url = None;
+    let mut headers = Vec::new();
+    let mut iter = tokens.into_iter();
+    while let Some(token) = iter.next() {
+        match token.as_str() {
+            "-H" | "--header" => {
+                if let Some((name, value)) = iter.next().as_deref().and_then(|h| h.split_once(':'))
+                {
+                    headers.push((name.trim().to_string(), value.trim().to_string()));
+                }
+            }
+            "-b" | "--cookie" => {
+                if let Some(value) = iter.next() {
+                    headers.push(("Cookie".to_string(), value));
+                }
+            }
+            "--url" => url = iter.next(),
+            _ if token.starts_with("http://") || token.starts_with("https://") => url = Some(token),
+            _ => {}
+        }
+    }
+    let url = url.ok_or("no url found in the cURL command")?;
+    from_request(&url, &headers)
+}
+
+/// 从浏览器导出的HAR文件中找到最后一条quiz请求并提取配置
+pub fn from_har(har: &Value) -> Result<Credentials, Box<dyn Error>> {
+    let entries = har["log"]["entries"]
+        .as_array()
+        .ok_or("not a HAR file: no log.entries")?;
+    let mut found: Option<Credentials> = None;
+    for entry in entries {
+        let request = &entry["request"];
+        let url = match request["url"].as_str() {
+            Some(url) if url.contains("/current-user/quiz/") => url,
+            _ => continue,
+        };
+        let mut headers = request["headers"]
+            .as_array()
+            .map(|headers| {
+                headers
+                    .iter()
+                    .filter_map(|h| Some((h["name"].as_str()?, h["value"].as_str()?)))
+                    .map(|(name, value)| (name.to_string(), value.to_string()))
+                    .collect::<Vec<(String, String)>>()
+            })
+            .unwrap_or_default();
+        // 部分浏览器导出时不带cookie头，只有request.cookies
+        if !headers
+            .iter()
+            .any(|(name, _)| name.eq_ignore_ascii_case("cookie"))
+        {
+            let cookie = request["cookies"]
+                .as_array()
+                .map(|cookies| {
+                    cookies
+                        .iter()
+                        .filter_map(|c| {
+                            Some(format!("{}={}", c["name"].as_str()?, c["value"].as_str()?))
+                        })
+                        .collect::<Vec<String>>()
+                        .join("; ")
+                })
+                .unwrap_or_default();
+            headers.push(("Cookie".to_string(), cookie));
+        }
+        if let Ok(credentials) = from_request(url, &headers) {
+            // 只有iteration请求的url上有studentComponentID和moduleID，保留之前找到的
+            let previous = found.take();
+            found = Some(Credentials {
+                student_component_id: credentials.student_component_id.or_else(|| {
+                    previous
+                        .as_ref()
+                        .and_then(|p| p.student_component_id.clone())
+                }),
+                module_id: credentials
+                    .module_id
+                    .or_else(|| previous.as_ref().and_then(|p| p.module_id.clone())),
+                ..credentials
+            });
+        }
+    }
+    found.ok_or_else(|| {
+        "no /current-user/quiz/ request with cookie and owasp_csrftoken found, \
+         export the HAR with sensitive data included"
+            .into()
+    })
+}
+
+/// 从一条quiz请求的url和请求头中提取配置
+fn from_request(url: &str, headers: &[(String, String)]) -> Result<Credentials, Box<dyn Error>> {
+    let url = Url::parse(url)?;
+    let segments = url
+        .path_segments()
+        .map(|s| s.collect::<Vec<&str>>())
+        .unwrap_or_default();
+    let index = segments
+        .windows(3)
+        .position(|w| w[0] == "current-user" && w[1] == "quiz" && !w[2].is_empty())
+        .ok_or_else(|| format!("{} is not a /current-user/quiz/{{id}}/... request", url))?;
+    let quiz_id = segments[index + 2].to_string();
+    let api_prefix = "/".to_string() + &segments[..index].join("/");
+    let api_host = match (url.host_str(), url.port()) {
+        (Some(host), Some(port)) => format!("{}:{}", host, port),
+        (Some(host), None) => host.to_string(),
+        (None, _) => return Err(format!("{} has no host", url).into()),
+    };
+    let header = |name: &str| {
+        headers
+            .iter()
+            .rev()
+            .find(|(n, _)| n.eq_ignore_ascii_case(name))
+            .map(|(_, v)| v.clone())
+            .filter(|v| !v.is_empty())
+    };
+    let cookie = header("cookie").ok_or("no Cookie header in the request")?;
+    let csrf_token = header("owasp_csrftoken").ok_or("no owasp_csrftoken header in the request")?;
+
+    let query = |name: &str| {
+        url.query_pairs()
+            .find(|(k, _)| k == name)
+            .map(|(_, v)| v.to_string())
+    };
+    let jar = cookies(&cookie);
+    Ok(Credentials {
+        quiz_id,
+        student_component_id: query("studentComponentID")
+            .or_else(|| jar.get("PSA_STUD_CPNT_ID").cloned()),
+        module_id: query("moduleID").or_else(|| jar.get("PSA_STUD_CPNT_MOD_ID").cloned()),
+        cookie: cookie.into(),
+        csrf_token: csrf_token.into(),
+        api_scheme: url.scheme().to_string(),
+        api_host,
+        api_prefix,
+    })
+}
+
+/// 按shell的规则拆分命令，支持单引号、双引号、`$'...'`、反斜杠续行，以及cmd中的`^`转义
+fn shell_words(command: &str) -> Vec<String> {
+    let command = if command.contains("^\"") {
+        // cmd格式：^为转义字符，行尾的^为续行
+        let mut unescaped = String::new();
+        let mut chars = command.chars();
+        while let Some(c) = chars.next() {
+            match c {
+                '^' => match chars.next() {
+                    Some('\n') | Some('\r') | None => {}
+                    Some(next) => unescaped.push(next),
+                },
+                _ => unescaped.push(c),
+            }
+        }
+        unescaped
+    } else {
+        command.to_string()
+    };
+
+    let mut words = Vec::new();
+    let mut word: Option<String> = None;
+    let mut chars = command.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            c if c.is_whitespace() => {
+                if let Some(w) = word.take() {
+                    words.push(w);
+                }
+            }
+            '\\' => match chars.next() {
+                Some('\n') => {}
+                Some('\r') => {
+                    chars.next_if_eq(&'\n');
+                }
+                Some(next) => word.get_or_insert_with(String::new).push(next),
+                None => {}
+            },
+            '\'' => {
+                let w = word.get_or_insert_with(String::new);
+                for c in chars.by_ref() {
+                    if c == '\'' {
+                        break;
+                    }
+                    w.push(c);
+                }
+            }
+            '"' => {
+                let w = word.get_or_insert_with(String::new);
+                while let Some(c) = chars.next() {
+                    match c {
+                        '"' => break,
+                        '\\' => match chars.peek() {
+                            Some('"') | Some('\\') | Some('$') | Some('`') => {
+                                w.push(chars.next().expect("peeked"))
+                            }
+                            Some('\n') => {
+                                chars.next();
+                            }
+                            _ => w.push(c),
+                        },
+                        _ => w.push(c),
+                    }
+                }
+            }
+            '$' if chars.peek() == Some(&'\'') => {
+                chars.next();
+                let w = word.get_or_insert_with(String::new);
+                while let Some(c) = chars.next() {
+                    match c {
+                        '\'' => break,
+                        '\\' => match chars.next() {
+                            Some('n') => w.push('\n'),
+                            Some('t') => w.push('\t'),
+                            Some('r') => w.push('\r'),
+                            Some(next) => w.push(next),
+                            None => {}
+                        },
+                        _ => w.push(c),
+                    }
+                }
+            }
+            _ => word.get_or_insert_with(String::new).push(c),
+        }
+    }
+    if let Some(w) = word {
+        words.push(w);
+    }
+    words
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::credentials::{extract, from_curl, Credentials};
+    use wbl_robot::Secret;
+
+    const URL: &str = "https://eygsl.plateau.com/learning/user/api/v1/current-user/quiz/82f931a4-b474-4da3-9e6f-a081183e305a/iteration/English?studentComponentID=35792014&moduleID=653819&generateIteration=true";
+    const COOKIE: &str =
+        "JSESSIONID=C6588F49; PSA_STUD_CPNT_ID=35792014; PSA_STUD_CPNT_MOD_ID=653819";
+
+    fn expected() -> Credentials {
+        Credentials {
+            quiz_id: "82f931a4-b474-4da3-9e6f-a081183e305a".to_string(),
+            cookie: Secret::new(COOKIE),
+            csrf_token: Secret::new("YAM7-FSWU-Y766"),
+            student_component_id: Some("35792014".to_string()),
+            module_id: Some("653819".to_string()),
+            api_scheme: "https".to_string(),
+            api_host: "eygsl.plateau.com".to_string(),
+            api_prefix: "/learning/user/api/v1".to_string(),
+        }
+    }
+
+    #[test]
+    fn from_bash_curl_test() {
+        let command = format!(
+            "curl '{}' \\\n  -H 'accept: application/json' \\\n  -H $'cookie: {}' \\\n  -H 'owasp_csrftoken: YAM7-FSWU-Y766' \\\n  --compressed",
+            URL, COOKIE
+        );
+        assert_eq!(from_curl(&command).unwrap(), expected());
+    }
+
+    #[test]
+    fn from_cmd_curl_test() {
+        let command = format!(
+            "curl ^\"{}^\" ^\n  -b ^\"{}^\" ^\n  -H ^\"owasp_csrftoken: YAM7-FSWU-Y766^\"",
+            URL, COOKIE
+        );
+        assert_eq!(from_curl(&command).unwrap(), expected());
+    }
+
+    #[test]
+    fn from_har_test() {
+        let start_url = "https://eygsl.plateau.com/learning/user/api/v1/current-user/quiz/82f931a4-b474-4da3-9e6f-a081183e305a/iteration/0fffb7f4/question/69d37cc0?localeID=English";
+        let har = serde_json::json!({"log": {"entries": [
+            {"request": {"url": "https://eygsl.plateau.com/learning/user/api/v2/i18n/currentUserPreferences", "headers": []}},
+            {"request": {"url": URL, "headers": [
+                {"name": "Cookie", "value": COOKIE},
+                {"name": "owasp_csrftoken", "value": "YAM7-FSWU-Y766"}
+            ]}},
+            {"request": {"url": start_url, "headers": [
+                {"name": "owasp_csrftoken", "value": "YAM7-FSWU-Y766"}
+            ], "cookies": [
+                {"name": "JSESSIONID", "value": "C6588F49"},
+                {"name": "PSA_STUD_CPNT_ID", "value": "35792014"},
+                {"name": "PSA_STUD_CPNT_MOD_ID", "value": "653819"}
+            ]}}
+        ]}});
+        assert_eq!(extract(&har.to_string()).unwrap(), expected());
+    }
+
+    #[test]
+    fn wrong_request_test() {
+        let command = "curl 'https://eygsl.plateau.com/learning/user/api/v2/i18n/currentUserPreferences' -H 'cookie: a=b' -H 'owasp_csrftoken: t'";
+        assert!(from_curl(command).is_err());
+        let command = format!("curl '{}' -H 'cookie: {}'", URL, COOKIE);
+        assert!(from_curl(&command).is_err());
+    }
+}
@@ -0,0 +1,241 @@
+use std::collections::BTreeMap;
+use std::fmt;
+
+use crate::global::{compare_multiple_choices, MemoryStoreQuestion};
+use crate::response::AnswerChoice;
+use crate::store::collapse_duplicates;
+
+/// 两份题库之间一个题目的变化
+#[derive(Debug, PartialEq)]
+pub enum Change {
+    /// 新出现的题目
+    Added,
+    /// 之前不知道正确答案，现在知道了
+    Learned { correct: Vec<String> },
+    /// 新排除的错误选项或多选题的错误组合
+    Eliminated { choices: Vec<String> },
+    /// 已知的正确答案变了
+    Changed { old: Vec<String>, new: Vec<String> },
+    /// 已知的正确答案没有了
+    Forgotten { old: Vec<String> },
+    /// 已排除的选项或组合不再被排除
+    Unexcluded { choices: Vec<String> },
+    /// 题目不见了
+    Removed,
+}
+
+impl Change {
+    /// 之前已有的了解变了或丢了
+    pub fn is_regression(&self) -> bool {
+        matches!(
+            self,
+            Change::Changed { .. }
+                | Change::Forgotten { .. }
+                | Change::Unexcluded { .. }
+                | Change::Removed
+        )
+    }
+}
+
+impl fmt::Display for Change {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Change::Added => write!(f, "new question"),
+            Change::Learned { correct } => write!(f, "learned correct: {}", correct.join(" + ")),
+            Change::Eliminated { choices } => write!(f, "eliminated: {}", choices.join(" + ")),
+            Change::Changed { old, new } => write!(
+                f,
+                "correct changed from {} to {}",
+                old.join(" + "),
+                new.join(" + ")
+            ),
+            Change::Forgotten { old } => write!(f, "correct forgotten: {}", old.join(" + ")),
+            Change::Unexcluded { choices } => {
+                write!(f, "no longer eliminated: {}", choices.join(" + "))
+            }
+            Change::Removed => write!(f, "question removed"),
+        }
+    }
+}
+
+/// (useful_id, locale)
+pub type Key = (String, String);
+
+fn values(choices: &[AnswerChoice]) -> Vec<String> {
+    choices
+        .iter()
+        .map(|c| c.choice_value.trim().to_string())
+        .collect()
+}
+
+/// 比较两份题库，按题目排序返回每题的变化，同一题库中的重复题目先合并
+pub fn diff_banks(
+    old: Vec<MemoryStoreQuestion>,
+    new: Vec<MemoryStoreQuestion>,
+) -> Vec<(Key, Change)> {
+    let index = |questions: Vec<MemoryStoreQuestion>| {
+        collapse_duplicates(questions)
+            .0
+            .into_iter()
+            .map(|q| ((q.useful_id.clone(), q.locale.clone()), q))
+            .collect::<BTreeMap<Key, MemoryStoreQuestion>>()
+    };
+    let old = index(old);
+    let new = index(new);
+
+    let mut changes = Vec::new();
+    for (key, question) in new.iter() {
+        let before = match old.get(key) {
+            Some(before) => before,
+            None => {
+                changes.push((key.clone(), Change::Added));
+                if !question.correct_choices.is_empty() {
+                    changes.push((
+                        key.clone(),
+                        Change::Learned {
+                            correct: values(&question.correct_choices),
+                        },
+                    ));
+                }
+                continue;
+            }
+        };
+        diff_question(before, question)
+            .into_iter()
+            .for_each(|change| changes.push((key.clone(), change)));
+    }
+    for key in old.keys() {
+        if !new.contains_key(key) {
+            changes.push((key.clone(), Change::Removed));
+        }
+    }
+    changes.sort_by(|a, b| a.0.cmp(&b.0));
+    changes
+}
+
+fn diff_question(old: &MemoryStoreQuestion, new: &MemoryStoreQuestion) -> Vec<Change> {
+    let mut changes = Vec::new();
+    match (
+        old.correct_choices.is_empty(),
+        new.correct_choices.is_empty(),
+    ) {
+        (true, false) => changes.push(Change::Learned {
+            correct: values(&new.correct_choices),
+        }),
+        (false, true) => changes.push(Change::Forgotten {
+            old: values(&old.correct_choices),
+        }),
+        (false, false) if !compare_multiple_choices(&old.correct_choices, &new.correct_choices) => {
+            changes.push(Change::Changed {
+                old: values(&old.correct_choices),
+                new: values(&new.correct_choices),
+            })
+        }
+        _ => {}
+    }
+
+    let contains =
+        |list: &[AnswerChoice], choice: &AnswerChoice| list.iter().any(|c| c.same_choice(choice));
+    for choice in new.error_choices.iter() {
+        if !contains(&old.error_choices, choice) {
+            changes.push(Change::Eliminated {
+                choices: values(std::slice::from_ref(choice)),
+            });
+        }
+    }
+    for choice in old.error_choices.iter() {
+        if !contains(&new.error_choices, choice) {
+            changes.push(Change::Unexcluded {
+                choices: values(std::slice::from_ref(choice)),
+            });
+        }
+    }
+    let contains_group = |groups: &[Vec<AnswerChoice>], choices: &[AnswerChoice]| {
+        groups.iter().any(|g| compare_multiple_choices(g, choices))
+    };
+    for choices in new.multiple_error_choices.iter() {
+        if !contains_group(&old.multiple_error_choices, choices) {
+            changes.push(Change::Eliminated {
+                choices: values(choices),
+            });
+        }
+    }
+    for choices in old.multiple_error_choices.iter() {
+        if !contains_group(&new.multiple_error_choices, choices) {
+            changes.push(Change::Unexcluded {
+                choices: values(choices),
+            });
+        }
+    }
+    changes
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::diff::{diff_banks, Change};
+    use crate::global::MemoryStoreQuestion;
+
+    #[test]
+    fn diff_banks_test() {
+        let old = vec![
+            MemoryStoreQuestion::mock("q1", &["1", "2", "3"]).with_errors(&["1"]),
+            MemoryStoreQuestion::mock("q2", &["1", "2", "3"]).with_correct(&["2"]),
+            MemoryStoreQuestion::mock("q3", &["1", "2", "3"]).with_correct(&["3"]),
+            MemoryStoreQuestion::mock("q4", &["1", "2", "3"]),
+            MemoryStoreQuestion::mock("q4", &["1", "2", "3"]).with_errors(&["2"]),
+        ];
+        let new = vec![
+            MemoryStoreQuestion::mock("q1", &["1", "2", "3"])
+                .with_correct(&["3"])
+                .with_errors(&["1", "2"]),
+            MemoryStoreQuestion::mock("q2", &["1", "2", "3"]).with_correct(&["1"]),
+            MemoryStoreQuestion::mock("q4", &["1", "2", "3"]),
+            MemoryStoreQuestion::mock("q5", &["1", "2", "3"]).with_correct(&["1"]),
+        ];
+        let changes = diff_banks(old, new)
+            .into_iter()
+            .map(|((useful_id, _), change)| (useful_id, change))
+            .collect::<Vec<(String, Change)>>();
+        let strings = |values: &[&str]| values.iter().map(|v| v.to_string()).collect();
+        assert_eq!(
+            changes,
+            vec![
+                (
+                    "q1".to_string(),
+                    Change::Learned {
+                        correct: strings(&["3"])
+                    }
+                ),
+                (
+                    "q1".to_string(),
+                    Change::Eliminated {
+                        choices: strings(&["2"])
+                    }
+                ),
+                (
+                    "q2".to_string(),
+                    Change::Changed {
+                        old: strings(&["2"]),
+                        new: strings(&["1"])
+                    }
+                ),
+                ("q3".to_string(), Change::Removed),
+                // 旧题库中重复的q4合并后排除了2
+                (
+                    "q4".to_string(),
+                    Change::Unexcluded {
+                        choices: strings(&["2"])
+                    }
+                ),
+                ("q5".to_string(), Change::Added),
+                (
+                    "q5".to_string(),
+                    Change::Learned {
+                        correct: strings(&["1"])
+                    }
+                ),
+            ]
+        );
+        assert_eq!(changes.iter().filter(|(_, c)| c.is_regression()).count(), 3);
+    }
+}
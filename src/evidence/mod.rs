@@ -0,0 +1,377 @@
+use std::collections::HashMap;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use serde::{Deserialize, Serialize};
+
+use crate::global::MemoryStoreQuestion;
+use crate::response::{AnswerChoice, QuestionFormat};
+
+/// 一次答题的原始记录，题目上的每条了解都可以追溯到推出它的答题
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct Attempt {
+    /// 本次答题的studentAssessmentSysGUID
+    pub iteration_id: String,
+    pub quiz_id: String,
+    pub locale: String,
+    /// 提交的时间，unix时间戳（毫秒），同一秒内的多次答题也能按顺序重放
+    pub timestamp: u64,
+    pub score: usize,
+    pub questions_correct: usize,
+    pub answers: Vec<AttemptAnswer>,
+}
+
+/// 一次答题中某一题选择的选项
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct AttemptAnswer {
+    pub useful_id: String,
+    pub(crate) chosen_choices: Vec<AnswerChoice>,
+}
+
+/// 题目上记录的了解
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq)]
+pub enum Fact {
+    /// 正确选项
+    Correct,
+    /// 单选题的错误选项
+    Error,
+    /// 多选题错误的选项组合
+    MultipleError,
+    /// 记录被隔离，attempt及更早的、答过这一题的答题不再用于推导
+    Quarantined,
+}
+
+/// 一条了解的来源：哪次答题推出了哪些选项是正确或错误的
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct Evidence {
+    pub fact: Fact,
+    /// 选项的身份，见AnswerChoice::choice_id
+    pub choices: Vec<String>,
+    /// 推出这条了解的答题的iteration_id
+    pub attempt: String,
+}
+
+impl Evidence {
+    pub fn new(fact: Fact, choices: &[AnswerChoice], attempt: &str) -> Evidence {
+        Evidence {
+            fact,
+            choices: choices.iter().map(|c| c.choice_id()).collect(),
+            attempt: attempt.to_string(),
+        }
+    }
+}
+
+/// 单选题的一次尝试：哪次答题选了哪个选项，用于按固定顺序轮流尝试剩余的选项
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct Trial {
+    /// 选项的身份，见AnswerChoice::choice_id
+    pub choice: String,
+    /// 尝试的答题的iteration_id
+    pub attempt: String,
+}
+
+impl Trial {
+    pub fn new(choice: &AnswerChoice, attempt: &str) -> Trial {
+        Trial {
+            choice: choice.choice_id(),
+            attempt: attempt.to_string(),
+        }
+    }
+}
+
+/// 被隔离的题目及隔离时最后一次答到它的答题的时间，这些答题推出过自相矛盾的了解，重放和求解时跳过
+pub fn quarantined_until(
+    questions: &[MemoryStoreQuestion],
+    attempts: &[Attempt],
+) -> HashMap<(String, String), u64> {
+    let mut until = HashMap::new();
+    for question in questions {
+        for evidence in question.evidence.iter() {
+            if evidence.fact != Fact::Quarantined {
+                continue;
+            }
+            if let Some(attempt) = attempts.iter().find(|a| a.iteration_id == evidence.attempt) {
+                let key = (question.useful_id.clone(), question.locale.clone());
+                let time = until.entry(key).or_insert(attempt.timestamp);
+                *time = attempt.timestamp.max(*time);
+            }
+        }
+    }
+    until
+}
+
+/// 答题是否答过隔离前的某个被隔离的题目
+pub fn before_quarantine(attempt: &Attempt, until: &HashMap<(String, String), u64>) -> bool {
+    attempt.answers.iter().any(|answer| {
+        until
+            .get(&(answer.useful_id.clone(), attempt.locale.clone()))
+            .is_some_and(|time| attempt.timestamp <= *time)
+    })
+}
+
+pub fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis() as u64)
+        .unwrap_or_default()
+}
+
+/// 逐条列出题目上的了解及推出它们的答题，用于追查错误的了解从何而来
+pub fn explain(question: &MemoryStoreQuestion, attempts: &[Attempt]) -> Vec<String> {
+    let text = |id: &String| {
+        question
+            .choices
+            .iter()
+            .find(|c| &c.choice_id() == id)
+            .map(|c| c.choice_value.clone())
+            .unwrap_or_else(|| id.clone())
+    };
+    let mut used = vec![false; question.evidence.len()];
+    let mut sources = |fact: Fact, choices: &[AnswerChoice]| {
+        let mut ids = choices.iter().map(|c| c.choice_id()).collect::<Vec<_>>();
+        ids.sort();
+        let mut lines = Vec::new();
+        for (index, evidence) in question.evidence.iter().enumerate() {
+            let mut evidence_ids = evidence.choices.clone();
+            evidence_ids.sort();
+            let matches = match fact {
+                Fact::Error => evidence.fact == fact && evidence_ids.contains(&ids[0]),
+                _ => evidence.fact == fact && evidence_ids == ids,
+            };
+            if matches {
+                used[index] = true;
+                lines.push(format!("    {}", describe(&evidence.attempt, attempts)));
+            }
+        }
+        if lines.is_empty() {
+            lines.push(
+                "    no recorded evidence, learned before attempts were recorded".to_string(),
+            );
+        }
+        lines
+    };
+
+    let mut lines = Vec::new();
+    if !question.correct_choices.is_empty() {
+        let values = question
+            .correct_choices
+            .iter()
+            .map(|c| c.choice_value.clone());
+        lines.push(format!(
+            "correct: {}",
+            values.collect::<Vec<_>>().join(" + ")
+        ));
+        lines.extend(sources(Fact::Correct, &question.correct_choices));
+    }
+    for choice in question.error_choices.iter() {
+        lines.push(format!("error: {}", choice.choice_value));
+        lines.extend(sources(Fact::Error, std::slice::from_ref(choice)));
+    }
+    for choices in question.multiple_error_choices.iter() {
+        let values = choices.iter().map(|c| c.choice_value.clone());
+        lines.push(format!(
+            "multiple error: {}",
+            values.collect::<Vec<_>>().join(" + ")
+        ));
+        lines.extend(sources(Fact::MultipleError, choices));
+    }
+    // 与现有了解不符的来源，例如被合并题库时的冲突处理丢弃的答案
+    for (index, evidence) in question.evidence.iter().enumerate() {
+        if evidence.fact == Fact::Quarantined {
+            lines.push("quarantined, earlier attempts are ignored".to_string());
+            lines.push(format!(
+                "    up to {}",
+                describe(&evidence.attempt, attempts)
+            ));
+        } else if !used[index] {
+            let values = evidence.choices.iter().map(text).collect::<Vec<_>>();
+            lines.push(format!(
+                "superseded {:?}: {}",
+                evidence.fact,
+                values.join(" + ")
+            ));
+            lines.push(format!("    {}", describe(&evidence.attempt, attempts)));
+        }
+    }
+    lines
+}
+
+/// 一次答题的概况
+fn describe(iteration_id: &str, attempts: &[Attempt]) -> String {
+    match attempts.iter().find(|a| a.iteration_id == iteration_id) {
+        Some(attempt) => format!(
+            "from attempt {} at {}: score {}, {} of {} correct",
+            attempt.iteration_id,
+            httpdate::fmt_http_date(UNIX_EPOCH + Duration::from_millis(attempt.timestamp)),
+            attempt.score,
+            attempt.questions_correct,
+            attempt.answers.len()
+        ),
+        None => format!(
+            "from attempt {}, which is not recorded in the store",
+            iteration_id
+        ),
+    }
+}
+
+/// 按时间顺序重放所有答题记录，用当前的推导规则重新得到题库。
+/// questions提供题目本身（选项和题目信息），其中已有的了解会被丢弃，没有出现在答题记录中的题目不会出现在结果中。
+/// 被隔离的题目保留隔离的标记，隔离前答过它的答题被跳过
+pub fn rebuild(
+    questions: &[MemoryStoreQuestion],
+    attempts: &[Attempt],
+) -> Vec<MemoryStoreQuestion> {
+    let templates = questions
+        .iter()
+        .map(|q| ((q.useful_id.as_str(), q.locale.as_str()), q))
+        .collect::<HashMap<_, _>>();
+    let until = quarantined_until(questions, attempts);
+    let mut attempts = attempts.iter().collect::<Vec<&Attempt>>();
+    attempts.sort_by_key(|a| a.timestamp);
+
+    let mut rebuilt: Vec<MemoryStoreQuestion> = Vec::new();
+    for attempt in attempts {
+        if before_quarantine(attempt, &until) {
+            continue;
+        }
+        let mut answered = Vec::new();
+        for answer in attempt.answers.iter() {
+            let known = rebuilt
+                .iter()
+                .find(|q| q.useful_id == answer.useful_id && q.locale == attempt.locale);
+            let mut question = match (known, templates.get(&(&answer.useful_id, &attempt.locale))) {
+                (Some(known), _) => known.clone(),
+                (None, Some(template)) => {
+                    let mut question = (*template).clone();
+                    question.correct_choices.clear();
+                    question.error_choices.clear();
+                    question.multiple_error_choices.clear();
+                    question.evidence.retain(|e| e.fact == Fact::Quarantined);
+                    question.tried_choices.clear();
+                    question
+                }
+                // 题目已经不在存储中，无从得知它的选项
+                (None, None) => continue,
+            };
+            question.chosen_choices = answer.chosen_choices.clone();
+            if question.format == QuestionFormat::MultiChoiceSingleAnswer
+                && question.correct_choices.is_empty()
+            {
+                for choice in question.chosen_choices.iter() {
+                    let trial = Trial::new(choice, &attempt.iteration_id);
+                    question.tried_choices.push(trial);
+                }
+            }
+            answered.push(question);
+        }
+        // 与答题时相同：满分时所选的都是正确的，答对的题数恰好等于已知答案的题数时其余题目所选的都是错误的
+        let known_correct = answered
+            .iter()
+            .filter(|q| !q.correct_choices.is_empty())
+            .count();
+        for question in answered.iter_mut() {
+            if attempt.score == 100 {
+                question.remember_correct(&attempt.iteration_id);
+            } else if known_correct == attempt.questions_correct {
+                question.remember_error(&attempt.iteration_id);
+            }
+            question.chosen_choices.clear();
+        }
+        for question in answered {
+            match rebuilt
+                .iter_mut()
+                .find(|q| q.useful_id == question.useful_id && q.locale == question.locale)
+            {
+                Some(existing) => *existing = question,
+                None => rebuilt.push(question),
+            }
+        }
+    }
+    rebuilt
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::evidence::{explain, rebuild, Attempt, AttemptAnswer, Evidence, Fact};
+    use crate::global::MemoryStoreQuestion;
+    use crate::response::AnswerChoice;
+
+    fn attempt(id: &str, timestamp: u64, score: usize, correct: usize, chosen: &[&str]) -> Attempt {
+        Attempt {
+            iteration_id: id.to_string(),
+            quiz_id: String::from("quiz"),
+            locale: String::from("English"),
+            timestamp,
+            score,
+            questions_correct: correct,
+            answers: ["q1", "q2"]
+                .iter()
+                .zip(chosen)
+                .map(|(useful_id, value)| AttemptAnswer {
+                    useful_id: useful_id.to_string(),
+                    chosen_choices: vec![AnswerChoice::from_mock(value)],
+                })
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn rebuild_test() {
+        let attempts = vec![
+            // 顺序打乱，重放时按时间排序
+            attempt("third", 30000, 100, 2, &["2", "3"]),
+            attempt("first", 10000, 0, 0, &["1", "1"]),
+            attempt("second", 20000, 50, 1, &["3", "2"]),
+        ];
+        let rebuilt = rebuild(
+            &[
+                MemoryStoreQuestion::mock("q1", &["1", "2", "3"]).with_correct(&["3"]),
+                MemoryStoreQuestion::mock("q2", &["1", "2", "3"]).with_correct(&["3"]),
+            ],
+            &attempts,
+        );
+        assert_eq!(rebuilt.len(), 2);
+
+        // first全错，两题的1都是错误选项；second答对的题数不等于已知答案的题数，推不出什么
+        let q1 = &rebuilt[0];
+        assert_eq!(q1.correct_choices, vec![AnswerChoice::from_mock("2")]);
+        assert_eq!(q1.error_choices, vec![AnswerChoice::from_mock("1")]);
+        assert_eq!(
+            q1.evidence,
+            vec![
+                Evidence::new(Fact::Error, &[AnswerChoice::from_mock("1")], "first"),
+                Evidence::new(Fact::Correct, &[AnswerChoice::from_mock("2")], "third"),
+            ]
+        );
+        // 三次答题都还不知道q1的答案，每次所选的都是一次尝试
+        assert_eq!(
+            q1.tried_choices
+                .iter()
+                .map(|t| t.attempt.as_str())
+                .collect::<Vec<&str>>(),
+            vec!["first", "second", "third"]
+        );
+        // 存储中原有的了解不会被沿用
+        assert_eq!(
+            rebuilt[1].correct_choices,
+            vec![AnswerChoice::from_mock("3")]
+        );
+        assert_eq!(rebuilt[1].evidence[1].attempt, "third");
+
+        let mut audited = q1.clone();
+        audited.correct_choices = vec![AnswerChoice::from_mock("3")];
+        audited.error_choices.push(AnswerChoice::from_mock("2"));
+        assert_eq!(
+            explain(&audited, &attempts[1..]),
+            vec![
+                "correct: 3",
+                "    no recorded evidence, learned before attempts were recorded",
+                "error: 1",
+                "    from attempt first at Thu, 01 Jan 1970 00:00:10 GMT: score 0, 0 of 2 correct",
+                "error: 2",
+                "    no recorded evidence, learned before attempts were recorded",
+                "superseded Correct: 2",
+                "    from attempt third, which is not recorded in the store",
+            ]
+        );
+    }
+}
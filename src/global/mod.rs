@@ -1,68 +1,234 @@
-use std::collections::HashMap;
 use std::error::Error;
+use std::path::Path;
 
-use mongodb::bson::doc;
-use mongodb::Collection;
 use rand::seq::SliceRandom;
 use rand::Rng;
 use serde::{Deserialize, Serialize};
+use serde_json::json;
 
-use wbl_robot::{cookies, env_get};
+use wbl_robot::{CookieJar, Secret};
 
-use crate::response::{AnswerChoice, QuestionFormat};
+use crate::config::Config;
+use crate::evidence::{now, Attempt, AttemptAnswer, Evidence, Fact, Trial};
+use crate::planner::{plan, probe_chance, Plan};
+use crate::response::{AnswerChoice, QuestionDetails, QuestionFormat};
+use crate::schema::SCHEMA_VERSION;
+use crate::store::QuestionStore;
+use crate::verify::verify;
 
 pub struct GlobalData {
     quiz_id: String,
-    cookie: String,
-    csrf_token: String,
-    cookies: HashMap<String, String>,
+    course_id: String,
+    quiz_sys_guid: String,
+    cookies: CookieJar,
+    csrf_token: Secret,
+    student_component_id: String,
+    module_id: String,
+    api_base: String,
+    locale: String,
     student_assessment_sys_guid: String,
     questions: Vec<MemoryStoreQuestion>,
+    // 本次答题中已探测的题目答对的概率
+    probes: Vec<f64>,
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
 pub struct MemoryStoreQuestion {
     pub useful_id: String,
     pub temp_id: String,
-    format: QuestionFormat,
-    choices: Vec<AnswerChoice>,
+    // 答题时使用的语言，同一题目不同语言的选项文字不同，分开记录
+    pub locale: String,
+    // 题目所属的课程（PSA_CPNT_ID中的课程id，与bank/下的题库文件名一致），不知道时为空
+    pub course_id: String,
+    // 答到题目的测验，即url中的quiz id和接口返回的quizSysGUID，不知道时为空
+    pub quiz_id: String,
+    pub quiz_sys_guid: String,
+    pub(crate) format: QuestionFormat,
+    // 题干、分值、版本和图片等题目本身的信息
+    pub(crate) details: QuestionDetails,
+    pub(crate) choices: Vec<AnswerChoice>,
     // 已知的正确选项
-    correct_choices: Vec<AnswerChoice>,
+    pub(crate) correct_choices: Vec<AnswerChoice>,
     // 已知的错误选项
-    error_choices: Vec<AnswerChoice>,
+    pub(crate) error_choices: Vec<AnswerChoice>,
     // 已知的多选题的错误选项
-    multiple_error_choices: Vec<Vec<AnswerChoice>>,
+    pub(crate) multiple_error_choices: Vec<Vec<AnswerChoice>>,
     // 本次选择的选项
-    chosen_choices: Vec<AnswerChoice>,
+    pub(crate) chosen_choices: Vec<AnswerChoice>,
+    // 每条了解来自哪次答题
+    pub(crate) evidence: Vec<Evidence>,
+    // 单选题不知道答案时每次答题尝试的选项
+    pub(crate) tried_choices: Vec<Trial>,
+    // 文档格式的版本，读取时旧版本的文档会先被schema::migrate升级
+    pub schema_version: u32,
 }
 
 impl MemoryStoreQuestion {
+    /// 合并另一份记录中对同一题目的了解：错误选项取并集，自己还不知道正确选项时采用对方的，
+    /// 题目信息中自己没有的字段也采用对方的，了解的来源和尝试过的选项取并集
+    pub fn merge_knowledge(&mut self, other: &MemoryStoreQuestion) {
+        self.details.fill(&other.details);
+        self.fill_namespace(&other.course_id, &other.quiz_id, &other.quiz_sys_guid);
+        for evidence in other.evidence.iter() {
+            if !self.evidence.contains(evidence) {
+                self.evidence.push(evidence.clone());
+            }
+        }
+        for trial in other.tried_choices.iter() {
+            if !self.tried_choices.contains(trial) {
+                self.tried_choices.push(trial.clone());
+            }
+        }
+        if self.correct_choices.is_empty() {
+            self.correct_choices = other.correct_choices.clone();
+        }
+        for choice in other.error_choices.iter() {
+            if !self.error_choices.iter().any(|c| c.same_choice(choice)) {
+                self.error_choices.push(choice.clone());
+            }
+        }
+        for choices in other.multiple_error_choices.iter() {
+            if !self
+                .multiple_error_choices
+                .iter()
+                .any(|m| compare_multiple_choices(m, choices))
+            {
+                self.multiple_error_choices.push(choices.clone());
+            }
+        }
+    }
+
+    /// 补上还不知道的课程和测验，已知的不会被覆盖
+    pub fn fill_namespace(&mut self, course_id: &str, quiz_id: &str, quiz_sys_guid: &str) {
+        for (own, other) in [
+            (&mut self.course_id, course_id),
+            (&mut self.quiz_id, quiz_id),
+            (&mut self.quiz_sys_guid, quiz_sys_guid),
+        ] {
+            if own.is_empty() {
+                *own = other.to_string();
+            }
+        }
+    }
+
+    /// 本次所选的是错误的（还不知道正确答案时），记下来源为attempt
+    pub fn remember_error(&mut self, attempt: &str) {
+        if !self.correct_choices.is_empty() {
+            return;
+        }
+        match self.format {
+            QuestionFormat::MultiChoiceSingleAnswer => {
+                // 有意选择的已知错误选项不重复记录
+                let new_errors = self
+                    .chosen_choices
+                    .iter()
+                    .filter(|cq| !self.error_choices.iter().any(|e| e.same_choice(cq)))
+                    .cloned()
+                    .collect::<Vec<AnswerChoice>>();
+                if new_errors.is_empty() {
+                    return;
+                }
+                self.error_choices.extend_from_slice(&new_errors);
+                self.evidence
+                    .push(Evidence::new(Fact::Error, &new_errors, attempt));
+                if self.excluded_errors().len() == 1 {
+                    self.correct_choices = self.excluded_errors();
+                    self.evidence.push(Evidence::new(
+                        Fact::Correct,
+                        &self.correct_choices,
+                        attempt,
+                    ));
+                    // todo 统计得到全部可能的选项数量，当error的记录已经跟全部可能只差一时，则可以得到剩下就是正确选项
+                }
+            }
+            QuestionFormat::MultiChoiceMultipleAnswer => {
+                let mut need_update = true;
+                self.multiple_error_choices.iter().for_each(|m| {
+                    if compare_multiple_choices(m, &self.chosen_choices) {
+                        need_update = false
+                    }
+                });
+                // 有意选择的已知错误组合不重复记录
+                if need_update {
+                    self.multiple_error_choices
+                        .push(self.chosen_choices.clone());
+                    self.evidence.push(Evidence::new(
+                        Fact::MultipleError,
+                        &self.chosen_choices,
+                        attempt,
+                    ));
+                }
+            }
+        }
+    }
+
+    /// 本次所选的是正确的，记下来源为attempt
+    pub fn remember_correct(&mut self, attempt: &str) {
+        self.correct_choices = self.chosen_choices.clone();
+        self.evidence
+            .push(Evidence::new(Fact::Correct, &self.correct_choices, attempt));
+    }
+
     /// 排除error_choices后剩余的选项
-    #[allow(dead_code)]
-    fn excluded_errors(&self) -> Vec<AnswerChoice> {
+    pub(crate) fn excluded_errors(&self) -> Vec<AnswerChoice> {
         let errors = self
             .error_choices
             .iter()
-            .map(|c| c.choice_value.clone())
+            .map(|c| c.choice_id())
             .collect::<Vec<String>>();
-        let exclude_errors = self
-            .choices
+        self.choices
             .clone()
             .into_iter()
-            .filter(|q| !errors.contains(&q.choice_value))
-            .collect::<Vec<AnswerChoice>>();
-        exclude_errors
-    }
-    /// 随机从所有choices里返回一个（已经排除error_choices）
-    #[allow(dead_code)]
-    fn random_choice(&self) -> AnswerChoice {
-        self.excluded_errors()
-            .choose(&mut rand::thread_rng())
-            .expect("process error, have not choices left")
-            .clone()
+            .filter(|q| !errors.contains(&q.choice_id()))
+            .collect::<Vec<AnswerChoice>>()
+    }
+    /// 将记录中的选项换成本次题目中的同一选项，提交的是服务端当前的选项，选项文字修改过也能对应
+    fn current_choices(&self, choices: &[AnswerChoice]) -> Vec<AnswerChoice> {
+        choices
+            .iter()
+            .map(|c| {
+                self.choices
+                    .iter()
+                    .find(|q| q.same_choice(c))
+                    .unwrap_or(c)
+                    .clone()
+            })
+            .collect()
+    }
+    /// 按轮次尝试未被排除的选项：每一轮中每个剩余选项都恰好试一次，试得最少的选项先试，
+    /// 剩余n个选项时最多n次答题就会选中正确的选项，不会因为得分推不出错误选项而重复同一个错误选项。
+    /// 同一轮中的顺序由题目和轮次决定，不同题目不会步调一致地轮换，否则同一次答题中的几道未知题目可能永远不会同时答对或同时答错
+    fn next_choice(&self) -> Option<AnswerChoice> {
+        let tries = |choice: &AnswerChoice| {
+            let id = choice.choice_id();
+            self.tried_choices.iter().filter(|t| t.choice == id).count()
+        };
+        let remaining = self.excluded_errors();
+        let round = remaining.iter().map(tries).min().unwrap_or_default();
+        remaining
+            .into_iter()
+            .filter(|c| tries(c) == round)
+            .min_by_key(|c| fnv1a(&[&self.useful_id, &round.to_string(), &c.choice_id()]))
+    }
+    /// 多选题还有没被排除的组合，n个选项共有2^n-1种非空的组合，错误组合已经去重
+    pub(crate) fn combinations_left(&self) -> bool {
+        !self.choices.is_empty()
+            && 1usize
+                .checked_shl(self.choices.len() as u32)
+                .is_none_or(|all| self.multiple_error_choices.len() < all - 1)
+    }
+    /// 记录的了解排除了本次所有的选项时忘掉错误的选项和组合，当作不知道答案的题目作答。
+    /// 存储中的记录合并时取并集，不会因此丢失
+    fn forget_errors(&mut self) {
+        println!(
+            "\x1b[31m[{}] every choice is eliminated, answering it as an unknown question\x1b[0m",
+            self.useful_id
+        );
+        self.error_choices.clear();
+        self.multiple_error_choices.clear();
+        self.tried_choices.clear();
     }
     /// 随机从所有choices里返回若干个
-    #[allow(dead_code)]
     fn random_choices(&self) -> Vec<AnswerChoice> {
         let len = self.choices.len();
         let mut result = self
@@ -73,10 +239,8 @@ impl MemoryStoreQuestion {
             .collect::<Vec<AnswerChoice>>();
         let mut same = false;
         self.multiple_error_choices.iter().for_each(|m| {
-            if !same {
-                if compare_multiple_choices(m, &result) {
-                    same = true;
-                }
+            if !same && compare_multiple_choices(m, &result) {
+                same = true;
             }
         });
 
@@ -89,10 +253,8 @@ impl MemoryStoreQuestion {
                 .cloned()
                 .collect::<Vec<AnswerChoice>>();
             self.multiple_error_choices.iter().for_each(|m| {
-                if !same {
-                    if compare_multiple_choices(m, &result) {
-                        same = true;
-                    }
+                if !same && compare_multiple_choices(m, &result) {
+                    same = true;
                 }
             });
         }
@@ -101,19 +263,78 @@ impl MemoryStoreQuestion {
     }
 }
 
+/// 测试用的一组选项，见AnswerChoice::from_mock
+#[cfg(test)]
+pub(crate) fn mock_choices(values: &[&str]) -> Vec<AnswerChoice> {
+    values.iter().map(|v| AnswerChoice::from_mock(v)).collect()
+}
+
+/// 各模块测试共用的题目，默认为English的单选题，没有任何了解，需要时再用with_*补上
+#[cfg(test)]
+impl MemoryStoreQuestion {
+    pub(crate) fn mock(useful_id: &str, values: &[&str]) -> MemoryStoreQuestion {
+        MemoryStoreQuestion {
+            useful_id: useful_id.to_string(),
+            temp_id: String::new(),
+            locale: String::from("English"),
+            course_id: String::new(),
+            quiz_id: String::new(),
+            quiz_sys_guid: String::new(),
+            format: QuestionFormat::MultiChoiceSingleAnswer,
+            details: QuestionDetails::default(),
+            choices: mock_choices(values),
+            correct_choices: Vec::new(),
+            error_choices: Vec::new(),
+            multiple_error_choices: Vec::new(),
+            chosen_choices: Vec::new(),
+            evidence: Vec::new(),
+            tried_choices: Vec::new(),
+            schema_version: SCHEMA_VERSION,
+        }
+    }
+
+    pub(crate) fn with_locale(mut self, locale: &str) -> MemoryStoreQuestion {
+        self.locale = locale.to_string();
+        self
+    }
+
+    pub(crate) fn with_format(mut self, format: QuestionFormat) -> MemoryStoreQuestion {
+        self.format = format;
+        self
+    }
+
+    pub(crate) fn with_correct(mut self, values: &[&str]) -> MemoryStoreQuestion {
+        self.correct_choices = mock_choices(values);
+        self
+    }
+
+    pub(crate) fn with_errors(mut self, values: &[&str]) -> MemoryStoreQuestion {
+        self.error_choices = mock_choices(values);
+        self
+    }
+}
+
+/// FNV-1a哈希，不随编译器版本变化，同一题目在任何机器上的尝试顺序都相同
+fn fnv1a(parts: &[&str]) -> u64 {
+    let mut hash: u64 = 0xcbf29ce484222325;
+    for part in parts {
+        for byte in part.bytes().chain(std::iter::once(0)) {
+            hash ^= byte as u64;
+            hash = hash.wrapping_mul(0x100000001b3);
+        }
+    }
+    hash
+}
+
 /// 比较两个多选题选择的选项是否相同
-#[allow(dead_code)]
-fn compare_multiple_choices(a: &Vec<AnswerChoice>, b: &Vec<AnswerChoice>) -> bool {
+pub(crate) fn compare_multiple_choices(a: &[AnswerChoice], b: &[AnswerChoice]) -> bool {
     if a.len() != b.len() {
         return false;
     }
-    let b_values = b
-        .iter()
-        .map(|c| c.choice_value.clone())
-        .collect::<Vec<String>>();
+    let b_values = b.iter().map(|c| c.choice_id()).collect::<Vec<String>>();
     let mut same = true;
     a.iter().for_each(|c| {
-        if !b_values.contains(&c.choice_value) {
+        if !b_values.contains(&c.choice_id()) {
             same = false;
         }
     });
@@ -143,36 +364,67 @@ fn get_combination_count(v: usize) -> usize {
 }
 
 impl GlobalData {
-    pub fn from_env() -> GlobalData {
-        let quiz_id = env_get("QUIZ_ID").expect("unable to find [QUIZ_ID] in .env file");
-        let cookie = env_get("COOKIE").expect("unable to find [COOKIE] in .env file");
-        let csrf_token = env_get("CSRF_TOKEN").expect("unable to find [CSRF_TOKEN] in .env file");
+    pub fn from_config(config: &Config) -> GlobalData {
         GlobalData {
-            quiz_id: quiz_id.clone(),
-            cookie: cookie.clone(),
-            csrf_token: csrf_token.clone(),
-            cookies: cookies(cookie),
+            quiz_id: config.quiz_id.clone(),
+            course_id: config.course_id.clone(),
+            quiz_sys_guid: "".to_string(),
+            cookies: CookieJar::from_header(config.cookie.expose()),
+            csrf_token: config.csrf_token.clone(),
+            student_component_id: config.student_component_id.clone(),
+            module_id: config.module_id.clone(),
+            api_base: config.api_base.clone(),
+            locale: config.locale.clone(),
             student_assessment_sys_guid: "".to_string(),
             questions: Vec::new(),
+            probes: Vec::new(),
         }
     }
 
+    /// 会话过期后换上新的cookie和csrf token，答题进度保持不变
+    pub fn refresh_credentials(&mut self, config: &Config) {
+        self.cookies = CookieJar::from_header(config.cookie.expose());
+        self.csrf_token = config.csrf_token.clone();
+    }
+
+    /// 将本次答题的进度写入文件，等待重新登录期间可以查看已经选了哪些答案
+    pub fn save_attempt(&self, path: &Path) -> Result<(), Box<dyn Error>> {
+        let attempt = json!({
+            "quiz_id": self.quiz_id,
+            "locale": self.locale,
+            "student_assessment_sys_guid": self.student_assessment_sys_guid,
+            "questions": self.questions,
+        });
+        std::fs::write(path, serde_json::to_string_pretty(&attempt)?)?;
+        Ok(())
+    }
+
     pub fn quiz_id(&self) -> &String {
         &self.quiz_id
     }
 
-    pub fn cookie(&self) -> &String {
-        &self.cookie
+    pub fn cookies(&self) -> &CookieJar {
+        &self.cookies
     }
 
-    pub fn csrf_token(&self) -> &String {
+    pub fn csrf_token(&self) -> &Secret {
         &self.csrf_token
     }
 
-    pub fn cookie_value(&self, key: &str) -> &String {
-        self.cookies
-            .get(key)
-            .expect("can not find [${key}] in coolies")
+    pub fn student_component_id(&self) -> &String {
+        &self.student_component_id
+    }
+
+    pub fn module_id(&self) -> &String {
+        &self.module_id
+    }
+
+    pub fn api_base(&self) -> &String {
+        &self.api_base
+    }
+
+    pub fn locale(&self) -> &String {
+        &self.locale
     }
 
     pub fn student_guid(&self) -> &String {
@@ -183,16 +435,28 @@ impl GlobalData {
         self.student_assessment_sys_guid = value
     }
 
+    pub fn set_quiz_sys_guid(&mut self, value: String) {
+        self.quiz_sys_guid = value
+    }
+
     pub fn insert_question(&mut self, temp_id: String) {
         self.questions.push(MemoryStoreQuestion {
             useful_id: "".to_string(),
             temp_id,
+            locale: self.locale.clone(),
+            course_id: self.course_id.clone(),
+            quiz_id: self.quiz_id.clone(),
+            quiz_sys_guid: self.quiz_sys_guid.clone(),
             format: QuestionFormat::MultiChoiceSingleAnswer,
+            details: QuestionDetails::default(),
             choices: Vec::new(),
             correct_choices: Vec::new(),
             error_choices: Vec::new(),
             multiple_error_choices: Vec::new(),
             chosen_choices: Vec::new(),
+            evidence: Vec::new(),
+            tried_choices: Vec::new(),
+            schema_version: SCHEMA_VERSION,
         })
     }
 
@@ -212,6 +476,14 @@ impl GlobalData {
             .format = format;
     }
 
+    pub fn set_details(&mut self, temp_id: String, details: QuestionDetails) {
+        self.questions
+            .iter_mut()
+            .find(|q| q.temp_id == temp_id)
+            .expect("fail to find")
+            .details = details;
+    }
+
     /// 得到本次答题理论最少能答对的题数，及已经知道答案的题目数量
     /// 当本次答题答对题数与已知答案的题数相同，则可知其他题目均答错
     pub fn get_min_correct(&self) -> usize {
@@ -237,48 +509,75 @@ impl GlobalData {
 
     pub async fn update_from_db(
         &mut self,
-        coll: &Collection<MemoryStoreQuestion>,
+        store: &dyn QuestionStore,
         temp_id: String,
     ) -> Result<(), Box<dyn Error>> {
-        let mut question = self
+        let question = self
             .questions
             .iter_mut()
             .find(|q| q.temp_id == temp_id)
             .expect("fail to find");
-        let db = coll
-            .find_one(doc! {"useful_id":question.useful_id.clone()}, None)
-            .await?;
-        match db {
-            Some(q) => {
-                question.correct_choices = q.correct_choices;
-                question.error_choices = q.error_choices;
-                question.multiple_error_choices = q.multiple_error_choices;
+        let db = store.find(&question.useful_id, &question.locale).await?;
+        if let Some(q) = db {
+            // 按本次的选项核对记录的了解：记录本身自洽，服务端修改或替换了选项后也可能无选项可选。
+            // 有问题时当作不知道答案的新题目作答
+            let mut merged = question.clone();
+            merged.correct_choices = q.correct_choices;
+            merged.error_choices = q.error_choices;
+            merged.multiple_error_choices = q.multiple_error_choices;
+            merged.tried_choices = q.tried_choices;
+            let problems = verify(&merged);
+            if !problems.is_empty() {
+                let problems = problems.iter().map(|p| p.to_string()).collect::<Vec<_>>();
+                println!(
+                    "\x1b[31m[{}] {}, answering it as an unknown question\x1b[0m",
+                    merged.useful_id,
+                    problems.join(", ")
+                );
+                return Ok(());
             }
-            None => {}
+            *question = merged;
         }
         Ok(())
     }
 
-    pub fn select_choices(&mut self, temp_id: String, multiple_target_error: bool) {
+    /// 按planner的安排选择本题的答案，题目按返回的顺序逐个选择。
+    /// 原来除最后一题外多选题总是选已知错误的组合（multiple_target_error），现在是否这样作答由planner按探测的收益决定
+    pub fn select_choices(&mut self, temp_id: String) {
+        let attempt = self.student_assessment_sys_guid.clone();
         let question = self
             .questions
             .iter_mut()
             .find(|q| q.temp_id == temp_id)
             .expect("fail to find");
-        if !question.correct_choices.is_empty() {
-            question.chosen_choices = question.correct_choices.clone();
-        } else {
-            match question.format {
-                QuestionFormat::MultiChoiceSingleAnswer => {
-                    question.chosen_choices.clear();
-                    question.chosen_choices.push(question.random_choice());
-                }
-                QuestionFormat::MultiChoiceMultipleAnswer => {
-                    if multiple_target_error && !question.multiple_error_choices.is_empty() {
-                        question.chosen_choices =
-                            question.multiple_error_choices.first().unwrap().clone()
-                    } else {
-                        question.chosen_choices = question.random_choices();
+        match plan(question, &self.probes) {
+            Plan::Correct => {
+                question.chosen_choices = question.current_choices(&question.correct_choices);
+            }
+            Plan::Mute(choices) => {
+                question.chosen_choices = choices;
+            }
+            Plan::Probe => {
+                self.probes.push(probe_chance(question));
+                match question.format {
+                    QuestionFormat::MultiChoiceSingleAnswer => {
+                        if question.next_choice().is_none() {
+                            question.forget_errors();
+                        }
+                        question.chosen_choices = Vec::new();
+                        if let Some(choice) = question.next_choice() {
+                            question.tried_choices.push(Trial::new(&choice, &attempt));
+                            question.chosen_choices = vec![choice];
+                        }
+                    }
+                    QuestionFormat::MultiChoiceMultipleAnswer => {
+                        if !question.combinations_left() {
+                            question.forget_errors();
+                        }
+                        question.chosen_choices = Vec::new();
+                        if question.combinations_left() {
+                            question.chosen_choices = question.random_choices();
+                        }
                     }
                 }
             }
@@ -299,7 +598,8 @@ impl GlobalData {
     }
 
     pub fn clear_question(&mut self) {
-        self.questions.clear()
+        self.questions.clear();
+        self.probes.clear();
     }
 
     pub fn first_question(&self) -> String {
@@ -311,119 +611,64 @@ impl GlobalData {
     }
 
     pub fn get_question(&self, index: usize) -> &MemoryStoreQuestion {
-        &self.questions.get(index).expect("fail to get")
+        self.questions.get(index).expect("fail to get")
     }
 
     pub fn last_question(&self) -> String {
         self.questions.last().expect("fail").temp_id.clone()
     }
 
+    /// 本次答题的记录，提交后保存到存储中作为推出了解的依据
+    pub fn attempt(&self, score: usize, questions_correct: usize) -> Attempt {
+        Attempt {
+            iteration_id: self.student_assessment_sys_guid.clone(),
+            quiz_id: self.quiz_id.clone(),
+            locale: self.locale.clone(),
+            timestamp: now(),
+            score,
+            questions_correct,
+            answers: self
+                .questions
+                .iter()
+                .map(|q| AttemptAnswer {
+                    useful_id: q.useful_id.clone(),
+                    chosen_choices: q.chosen_choices.clone(),
+                })
+                .collect(),
+        }
+    }
+
     pub fn remember_error(&mut self) {
-        self.questions.iter_mut().for_each(|q| {
-            if q.correct_choices.is_empty() {
-                match q.format {
-                    QuestionFormat::MultiChoiceSingleAnswer => {
-                        q.chosen_choices
-                            .iter()
-                            .for_each(|cq| q.error_choices.push(cq.clone()));
-                        if q.excluded_errors().len() == 1 {
-                            q.correct_choices = q.excluded_errors();
-                            // todo 统计得到全部可能的选项数量，当error的记录已经跟全部可能只差一时，则可以得到剩下就是正确选项
-                        }
-                    }
-                    QuestionFormat::MultiChoiceMultipleAnswer => {
-                        let mut need_update = true;
-                        q.multiple_error_choices.iter().for_each(|m| {
-                            if compare_multiple_choices(m, &q.chosen_choices) {
-                                need_update = false
-                            }
-                        });
-                        if need_update {
-                            q.multiple_error_choices.push(q.chosen_choices.clone());
-                        }
-                    }
-                }
-            }
-        });
+        let attempt = self.student_assessment_sys_guid.clone();
+        self.questions
+            .iter_mut()
+            .for_each(|q| q.remember_error(&attempt));
     }
 
     pub fn remember_correct(&mut self) {
-        self.questions.iter_mut().for_each(|q| {
-            q.correct_choices = q.chosen_choices.clone();
-        });
+        let attempt = self.student_assessment_sys_guid.clone();
+        self.questions
+            .iter_mut()
+            .for_each(|q| q.remember_correct(&attempt));
     }
 
-    pub async fn store_into_db(
-        &self,
-        coll: &Collection<MemoryStoreQuestion>,
-    ) -> Result<(), Box<dyn Error>> {
-        for i in 0..self.question_count() {
-            let current = self.get_question(i);
-            let db = coll
-                .find_one(doc! {"useful_id":current.useful_id.clone()}, None)
-                .await?;
-            match db {
-                Some(_) => {
-                    coll.delete_one(doc! {"useful_id":current.useful_id.clone()}, None)
-                        .await?;
-                }
-                None => {}
-            }
-            coll.insert_one(current.clone(), None).await?;
-        }
-        Ok(())
+    /// 将本次答题的了解合并进存储，不会覆盖其他同时运行的进程已保存的了解
+    pub async fn store_into_db(&self, store: &dyn QuestionStore) -> Result<(), Box<dyn Error>> {
+        store.merge_all(&self.questions).await
     }
 }
 
 #[cfg(test)]
+// 保持原有测试的写法
+#[allow(clippy::bool_assert_comparison, clippy::len_zero)]
 mod tests {
-    use crate::global::{compare_multiple_choices, get_combination_count, get_random};
-    use crate::response::{AnswerChoice, QuestionFormat};
-    use crate::MemoryStoreQuestion;
-
-    #[test]
-    fn random_choice_test() {
-        let mut question = MemoryStoreQuestion {
-            useful_id: String::from(""),
-            temp_id: String::from(""),
-            format: QuestionFormat::MultiChoiceSingleAnswer,
-            choices: vec![
-                AnswerChoice::from_mock("1"),
-                AnswerChoice::from_mock("2"),
-                AnswerChoice::from_mock("3"),
-                AnswerChoice::from_mock("4"),
-            ],
-            correct_choices: Vec::new(),
-            error_choices: Vec::new(),
-            multiple_error_choices: Vec::new(),
-            chosen_choices: Vec::new(),
-        };
-        let random_choice = question.random_choice().choice_value;
-        assert_eq!(
-            random_choice == String::from("1")
-                || random_choice == String::from("2")
-                || random_choice == String::from("3")
-                || random_choice == String::from("4"),
-            true
-        );
-        question.error_choices.push(AnswerChoice::from_mock("1"));
-        let random_choice = question.random_choice().choice_value;
-        assert_eq!(
-            random_choice == String::from("2")
-                || random_choice == String::from("3")
-                || random_choice == String::from("4"),
-            true
-        );
-        question.error_choices.push(AnswerChoice::from_mock("2"));
-        let random_choice = question.random_choice().choice_value;
-        assert_eq!(
-            random_choice == String::from("3") || random_choice == String::from("4"),
-            true
-        );
-        question.error_choices.push(AnswerChoice::from_mock("3"));
-        let random_choice = question.random_choice().choice_value;
-        assert_eq!(random_choice == String::from("4"), true);
-    }
+    use crate::bank::split_by_course;
+    use crate::evidence::Trial;
+    use crate::global::MemoryStoreQuestion;
+    use crate::global::{compare_multiple_choices, get_random, GlobalData};
+    use crate::response::{AnswerChoice, QuestionDetails, QuestionFormat};
+    use crate::schema::SCHEMA_VERSION;
+    use crate::store::{JsonStore, QuestionStore};
 
     #[test]
     fn get_random_test() {
@@ -458,7 +703,12 @@ mod tests {
         let mut question = MemoryStoreQuestion {
             useful_id: String::from(""),
             temp_id: String::from(""),
+            locale: String::from("English"),
+            course_id: String::new(),
+            quiz_id: String::new(),
+            quiz_sys_guid: String::new(),
             format: QuestionFormat::MultiChoiceSingleAnswer,
+            details: QuestionDetails::default(),
             choices: vec![
                 AnswerChoice::from_mock("1"),
                 AnswerChoice::from_mock("2"),
@@ -469,6 +719,9 @@ mod tests {
             error_choices: Vec::new(),
             multiple_error_choices: Vec::new(),
             chosen_choices: Vec::new(),
+            evidence: Vec::new(),
+            tried_choices: Vec::new(),
+            schema_version: SCHEMA_VERSION,
         };
         for _ in 0..50 {
             let random_choices = question.random_choices();
@@ -544,6 +797,203 @@ mod tests {
         }
     }
 
+    #[test]
+    fn current_choices_test() {
+        let mut fixed = AnswerChoice::from_mock("1");
+        fixed.choice_value = String::from("1 (fixed typo)");
+        let question = MemoryStoreQuestion {
+            useful_id: String::from(""),
+            temp_id: String::from(""),
+            locale: String::from("English"),
+            course_id: String::new(),
+            quiz_id: String::new(),
+            quiz_sys_guid: String::new(),
+            format: QuestionFormat::MultiChoiceSingleAnswer,
+            details: QuestionDetails::default(),
+            choices: vec![fixed.clone(), AnswerChoice::from_mock("2")],
+            correct_choices: Vec::new(),
+            error_choices: vec![AnswerChoice::from_mock("1")],
+            multiple_error_choices: Vec::new(),
+            chosen_choices: Vec::new(),
+            evidence: Vec::new(),
+            tried_choices: Vec::new(),
+            schema_version: SCHEMA_VERSION,
+        };
+        // 记录中的选项按GUID对应到本次的选项，提交的是修改后的文字
+        assert_eq!(
+            question.current_choices(&[AnswerChoice::from_mock("1")]),
+            vec![fixed]
+        );
+        assert_eq!(
+            question.excluded_errors(),
+            vec![AnswerChoice::from_mock("2")]
+        );
+    }
+
+    #[test]
+    fn next_choice_test() {
+        let mut question = MemoryStoreQuestion {
+            useful_id: String::from("q1"),
+            temp_id: String::from(""),
+            locale: String::from("English"),
+            course_id: String::new(),
+            quiz_id: String::new(),
+            quiz_sys_guid: String::new(),
+            format: QuestionFormat::MultiChoiceSingleAnswer,
+            details: QuestionDetails::default(),
+            choices: vec![
+                AnswerChoice::from_mock("1"),
+                AnswerChoice::from_mock("2"),
+                AnswerChoice::from_mock("3"),
+                AnswerChoice::from_mock("4"),
+            ],
+            correct_choices: Vec::new(),
+            error_choices: vec![AnswerChoice::from_mock("1")],
+            multiple_error_choices: Vec::new(),
+            chosen_choices: Vec::new(),
+            evidence: Vec::new(),
+            tried_choices: Vec::new(),
+            schema_version: SCHEMA_VERSION,
+        };
+        let try_next = |question: &mut MemoryStoreQuestion| {
+            let choice = question.next_choice().unwrap();
+            question.tried_choices.push(Trial::new(&choice, "attempt"));
+            choice.choice_value
+        };
+        // 得分推不出错误选项时，每一轮中三个剩余选项都各试一次，顺序是固定的
+        for _ in 0..3 {
+            let mut round = (0..3).map(|_| try_next(&mut question)).collect::<Vec<_>>();
+            round.sort();
+            assert_eq!(round, vec!["2", "3", "4"]);
+        }
+        let order = question
+            .tried_choices
+            .iter()
+            .map(|t| t.choice.clone())
+            .collect::<Vec<_>>();
+        question.tried_choices.clear();
+        (0..9).for_each(|_| {
+            try_next(&mut question);
+        });
+        assert_eq!(
+            question
+                .tried_choices
+                .iter()
+                .map(|t| t.choice.clone())
+                .collect::<Vec<_>>(),
+            order
+        );
+        // 试过的选项要等本轮其余的选项都试过后才会再试
+        question.tried_choices.truncate(1);
+        let tried = question.tried_choices[0].choice.clone();
+        assert_ne!(question.next_choice().unwrap().choice_id(), tried);
+        // 所有选项都被排除时没有可选的
+        question.error_choices = question.choices.clone();
+        assert_eq!(question.next_choice(), None);
+    }
+
+    #[tokio::test]
+    async fn changed_choices_test() {
+        let path =
+            std::env::temp_dir().join(format!("wbl-robot-changed-{}.json", std::process::id()));
+        let store = JsonStore::open(&path).unwrap();
+        // 记录本身自洽，但服务端去掉了没被排除的选项3，本次的选项都是错误的
+        let stored = MemoryStoreQuestion::mock("q1", &["1", "2", "3"]).with_errors(&["1", "2"]);
+        store.save(&stored).await.unwrap();
+        let mut global_data = GlobalData {
+            quiz_id: String::from("quiz"),
+            course_id: String::new(),
+            quiz_sys_guid: String::new(),
+            cookies: Default::default(),
+            csrf_token: Default::default(),
+            student_component_id: String::new(),
+            module_id: String::new(),
+            api_base: String::new(),
+            locale: String::from("English"),
+            student_assessment_sys_guid: String::from("attempt"),
+            questions: Vec::new(),
+            probes: Vec::new(),
+        };
+        global_data.insert_question(String::from("t1"));
+        global_data.set_useful_id(String::from("t1"), String::from("q1"));
+        global_data.set_choices(
+            String::from("t1"),
+            vec![AnswerChoice::from_mock("1"), AnswerChoice::from_mock("2")],
+        );
+        global_data
+            .update_from_db(&store, String::from("t1"))
+            .await
+            .unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert!(global_data.get_question(0).error_choices.is_empty());
+        global_data.select_choices(String::from("t1"));
+        assert_eq!(global_data.chosen_choices(String::from("t1")).len(), 1);
+
+        // 答题中途排除了所有选项时也当作不知道答案的题目作答，不会没有可选的
+        global_data.clear_question();
+        global_data.insert_question(String::from("t2"));
+        global_data.set_choices(
+            String::from("t2"),
+            vec![AnswerChoice::from_mock("1"), AnswerChoice::from_mock("2")],
+        );
+        global_data.questions[0].error_choices = global_data.questions[0].choices.clone();
+        global_data.select_choices(String::from("t2"));
+        assert_eq!(global_data.chosen_choices(String::from("t2")).len(), 1);
+        global_data.clear_question();
+        global_data.insert_question(String::from("t3"));
+        global_data.set_format(
+            String::from("t3"),
+            QuestionFormat::MultiChoiceMultipleAnswer,
+        );
+        global_data.set_choices(String::from("t3"), vec![AnswerChoice::from_mock("1")]);
+        global_data.questions[0].multiple_error_choices = vec![vec![AnswerChoice::from_mock("1")]];
+        global_data.select_choices(String::from("t3"));
+        assert_eq!(global_data.chosen_choices(String::from("t3")).len(), 1);
+    }
+
+    #[test]
+    fn namespace_test() {
+        let question = |useful_id: &str, course_id: &str| MemoryStoreQuestion {
+            useful_id: useful_id.to_string(),
+            temp_id: String::new(),
+            locale: String::from("English"),
+            course_id: course_id.to_string(),
+            quiz_id: String::new(),
+            quiz_sys_guid: String::new(),
+            format: QuestionFormat::MultiChoiceSingleAnswer,
+            details: QuestionDetails::default(),
+            choices: Vec::new(),
+            correct_choices: Vec::new(),
+            error_choices: Vec::new(),
+            multiple_error_choices: Vec::new(),
+            chosen_choices: Vec::new(),
+            evidence: Vec::new(),
+            tried_choices: Vec::new(),
+            schema_version: SCHEMA_VERSION,
+        };
+        // 已知的课程不会被覆盖，不知道的从另一份记录补上
+        let mut answered = question("q1", "HRC1");
+        answered.quiz_id = String::from("quiz");
+        let mut stored = question("q1", "");
+        stored.merge_knowledge(&answered);
+        assert_eq!(
+            (stored.course_id.as_str(), stored.quiz_id.as_str()),
+            ("HRC1", "quiz")
+        );
+        stored.fill_namespace("HRC2", "other", "guid");
+        assert_eq!(stored.course_id, "HRC1");
+        assert_eq!(stored.quiz_sys_guid, "guid");
+
+        let courses = split_by_course(vec![
+            question("q1", "HRC2"),
+            question("q2", ""),
+            question("q3", "HRC1"),
+            question("q4", "HRC2"),
+        ]);
+        assert_eq!(courses.keys().collect::<Vec<_>>(), vec!["HRC1", "HRC2"]);
+        assert_eq!(courses["HRC2"].len(), 2);
+    }
+
     #[test]
     fn get_combination_count_test() {
         // assert_eq!(get_combination_count(1), 1);
@@ -0,0 +1,382 @@
+use std::error::Error;
+use std::path::Path;
+
+use crate::global::MemoryStoreQuestion;
+use crate::response::{AnswerChoice, QuestionFormat};
+
+/// 一个课程的题目，来自bank/下的一个题库文件
+pub struct Course {
+    pub name: String,
+    pub questions: Vec<MemoryStoreQuestion>,
+}
+
+/// 对一个选项的了解
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum ChoiceState {
+    Correct,
+    /// 被排除的选项，已知正确答案时其余选项都是错误的
+    Eliminated,
+    Unknown,
+}
+
+impl ChoiceState {
+    fn label(&self) -> &'static str {
+        match self {
+            ChoiceState::Correct => "correct",
+            ChoiceState::Eliminated => "eliminated",
+            ChoiceState::Unknown => "unknown",
+        }
+    }
+}
+
+fn choice_states(question: &MemoryStoreQuestion) -> Vec<(&AnswerChoice, ChoiceState)> {
+    question
+        .choices
+        .iter()
+        .map(|choice| {
+            let state = if question
+                .correct_choices
+                .iter()
+                .any(|c| c.same_choice(choice))
+            {
+                ChoiceState::Correct
+            } else if !question.correct_choices.is_empty()
+                || question.error_choices.iter().any(|c| c.same_choice(choice))
+            {
+                ChoiceState::Eliminated
+            } else {
+                ChoiceState::Unknown
+            };
+            (choice, state)
+        })
+        .collect()
+}
+
+/// 题目的掌握情况
+fn status(question: &MemoryStoreQuestion) -> &'static str {
+    if !question.correct_choices.is_empty() {
+        "known"
+    } else if !question.error_choices.is_empty() || !question.multiple_error_choices.is_empty() {
+        "partial"
+    } else {
+        "unknown"
+    }
+}
+
+/// 没有记录题干的旧题目用questionID代替
+fn title(question: &MemoryStoreQuestion) -> String {
+    match &question.details.question_text {
+        Some(text) if !text.trim().is_empty() => text.trim().to_string(),
+        _ => format!("[{}]", question.useful_id),
+    }
+}
+
+fn format_name(question: &MemoryStoreQuestion) -> &'static str {
+    match question.format {
+        QuestionFormat::MultiChoiceSingleAnswer => "single answer",
+        QuestionFormat::MultiChoiceMultipleAnswer => "multiple answer",
+    }
+}
+
+fn choices_with(question: &MemoryStoreQuestion, state: ChoiceState) -> Vec<String> {
+    choice_states(question)
+        .into_iter()
+        .filter(|(_, s)| *s == state)
+        .map(|(c, _)| c.choice_value.trim().to_string())
+        .collect()
+}
+
+/// 多选题已排除的选项组合
+fn eliminated_combinations(question: &MemoryStoreQuestion) -> Vec<String> {
+    question
+        .multiple_error_choices
+        .iter()
+        .map(|choices| {
+            choices
+                .iter()
+                .map(|c| c.choice_value.trim())
+                .collect::<Vec<&str>>()
+                .join(" + ")
+        })
+        .collect()
+}
+
+/// 按输出文件的后缀选择格式：.csv、.md或.html
+pub fn render(path: &Path, courses: &[Course]) -> Result<String, Box<dyn Error>> {
+    let extension = path
+        .extension()
+        .map(|ext| ext.to_string_lossy().to_lowercase())
+        .unwrap_or_default();
+    match extension.as_str() {
+        "csv" => Ok(csv(courses)),
+        "md" | "markdown" => Ok(markdown(courses)),
+        "html" | "htm" => Ok(html(courses)),
+        _ => Err(format!(
+            "unable to tell the format of {}, use a .csv, .md or .html file",
+            path.display()
+        )
+        .into()),
+    }
+}
+
+/// 每题一行，同一格中的多个选项以 | 分隔
+pub fn csv(courses: &[Course]) -> String {
+    let mut lines = vec![[
+        "course",
+        "question_id",
+        "locale",
+        "format",
+        "question",
+        "status",
+        "correct",
+        "eliminated",
+        "eliminated_combinations",
+        "unknown",
+    ]
+    .join(",")];
+    for course in courses {
+        for question in course.questions.iter() {
+            let row = [
+                course.name.clone(),
+                question.useful_id.clone(),
+                question.locale.clone(),
+                format_name(question).to_string(),
+                title(question),
+                status(question).to_string(),
+                choices_with(question, ChoiceState::Correct).join(" | "),
+                choices_with(question, ChoiceState::Eliminated).join(" | "),
+                eliminated_combinations(question).join(" | "),
+                choices_with(question, ChoiceState::Unknown).join(" | "),
+            ];
+            lines.push(
+                row.iter()
+                    .map(|field| csv_field(field))
+                    .collect::<Vec<String>>()
+                    .join(","),
+            );
+        }
+    }
+    lines.join("\r\n") + "\r\n"
+}
+
+/// 以=、+、-、@、制表符或回车开头的内容在表格软件中可能被当作公式执行，前面加上'作为文本显示
+fn csv_field(field: &str) -> String {
+    let field = if field.starts_with(['=', '+', '-', '@', '\t', '\r']) {
+        format!("'{}", field)
+    } else {
+        field.to_string()
+    };
+    if field.contains([',', '"', '\r', '\n']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field
+    }
+}
+
+pub fn markdown(courses: &[Course]) -> String {
+    let mut out = String::from("# Study guide\n");
+    for course in courses {
+        out.push_str(&format!(
+            "\n## {} ({} questions)\n",
+            markdown_text(&course.name),
+            course.questions.len()
+        ));
+        for (index, question) in course.questions.iter().enumerate() {
+            out.push_str(&format!(
+                "\n### {}. {}\n\n_{}, {}, {}_\n\n",
+                index + 1,
+                markdown_text(&title(question)),
+                markdown_text(&question.useful_id),
+                question.locale,
+                format_name(question)
+            ));
+            for (choice, state) in choice_states(question) {
+                let text = markdown_text(choice.choice_value.trim());
+                out.push_str(&match state {
+                    ChoiceState::Correct => format!("- [x] **{}**\n", text),
+                    ChoiceState::Eliminated => format!("- [ ] ~~{}~~\n", text),
+                    ChoiceState::Unknown => format!("- [ ] {} _(unknown)_\n", text),
+                });
+            }
+            for combination in eliminated_combinations(question) {
+                out.push_str(&format!(
+                    "- eliminated combination: ~~{}~~\n",
+                    markdown_text(&combination)
+                ));
+            }
+        }
+    }
+    out
+}
+
+/// 转义markdown中有特殊含义的字符，选项文字中的换行合并为空格
+fn markdown_text(text: &str) -> String {
+    let mut out = String::new();
+    for c in text
+        .split_whitespace()
+        .collect::<Vec<&str>>()
+        .join(" ")
+        .chars()
+    {
+        if "\\`*_[]<>#|~".contains(c) {
+            out.push('\\');
+        }
+        out.push(c);
+    }
+    out
+}
+
+/// 不依赖外部资源的单个html页面
+pub fn html(courses: &[Course]) -> String {
+    let mut out = String::from(
+        "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n<title>Study guide</title>\n<style>\n\
+         body { font-family: sans-serif; max-width: 60em; margin: auto; padding: 1em; }\n\
+         .meta { color: #666; font-size: 0.9em; }\n\
+         .correct { color: #186a3b; font-weight: bold; }\n\
+         .eliminated { color: #999; text-decoration: line-through; }\n\
+         .unknown { color: #9a6700; }\n\
+         </style>\n</head>\n<body>\n<h1>Study guide</h1>\n",
+    );
+    for course in courses {
+        out.push_str(&format!(
+            "<section>\n<h2>{} ({} questions)</h2>\n",
+            html_text(&course.name),
+            course.questions.len()
+        ));
+        for (index, question) in course.questions.iter().enumerate() {
+            out.push_str(&format!(
+                "<h3>{}. {}</h3>\n<p class=\"meta\">{}, {}, {}, {}</p>\n<ul>\n",
+                index + 1,
+                html_text(&title(question)),
+                html_text(&question.useful_id),
+                html_text(&question.locale),
+                format_name(question),
+                status(question)
+            ));
+            for (choice, state) in choice_states(question) {
+                out.push_str(&format!(
+                    "<li class=\"{}\">{}</li>\n",
+                    state.label(),
+                    html_text(choice.choice_value.trim())
+                ));
+            }
+            for combination in eliminated_combinations(question) {
+                out.push_str(&format!(
+                    "<li class=\"eliminated\">{}</li>\n",
+                    html_text(&combination)
+                ));
+            }
+            out.push_str("</ul>\n");
+        }
+        out.push_str("</section>\n");
+    }
+    out.push_str("</body>\n</html>\n");
+    out
+}
+
+fn html_text(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&#39;")
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use crate::global::MemoryStoreQuestion;
+    use crate::guide::{
+        choice_states, csv, csv_field, html, markdown, render, ChoiceState, Course,
+    };
+    use crate::response::{AnswerChoice, QuestionDetails, QuestionFormat};
+    use crate::schema::SCHEMA_VERSION;
+
+    fn course() -> Course {
+        let mut known = MemoryStoreQuestion {
+            useful_id: String::from("q1"),
+            temp_id: String::new(),
+            locale: String::from("English"),
+            course_id: String::new(),
+            quiz_id: String::new(),
+            quiz_sys_guid: String::new(),
+            format: QuestionFormat::MultiChoiceSingleAnswer,
+            details: QuestionDetails::default(),
+            choices: vec![
+                AnswerChoice::from_mock("Yes, \"always\""),
+                AnswerChoice::from_mock("<No>"),
+                AnswerChoice::from_mock("Maybe"),
+            ],
+            correct_choices: vec![AnswerChoice::from_mock("Yes, \"always\"")],
+            error_choices: Vec::new(),
+            multiple_error_choices: Vec::new(),
+            chosen_choices: Vec::new(),
+            evidence: Vec::new(),
+            tried_choices: Vec::new(),
+            schema_version: SCHEMA_VERSION,
+        };
+        known.details.question_text = Some(String::from("Is it *true*?"));
+        let mut partial = known.clone();
+        partial.useful_id = String::from("q2");
+        partial.details.question_text = None;
+        partial.correct_choices = Vec::new();
+        partial.error_choices = vec![AnswerChoice::from_mock("<No>")];
+        Course {
+            name: String::from("HRC1"),
+            questions: vec![known, partial],
+        }
+    }
+
+    #[test]
+    fn choice_states_test() {
+        let course = course();
+        let states = |q: &MemoryStoreQuestion| {
+            choice_states(q)
+                .into_iter()
+                .map(|(_, s)| s)
+                .collect::<Vec<ChoiceState>>()
+        };
+        use ChoiceState::*;
+        assert_eq!(
+            states(&course.questions[0]),
+            vec![Correct, Eliminated, Eliminated]
+        );
+        assert_eq!(
+            states(&course.questions[1]),
+            vec![Unknown, Eliminated, Unknown]
+        );
+    }
+
+    #[test]
+    fn render_test() {
+        let courses = [course()];
+        let csv = csv(&courses);
+        let lines = csv.lines().collect::<Vec<&str>>();
+        assert_eq!(lines.len(), 3);
+        assert_eq!(
+            lines[1],
+            "HRC1,q1,English,single answer,Is it *true*?,known,\"Yes, \"\"always\"\"\",<No> | Maybe,,"
+        );
+        assert!(lines[2].starts_with("HRC1,q2,English,single answer,[q2],partial,,<No>,,"));
+        // 题目和选项中的公式不会被表格软件执行
+        assert_eq!(csv_field("=1+1"), "'=1+1");
+        assert_eq!(csv_field("@SUM(A1)"), "'@SUM(A1)");
+        assert_eq!(csv_field("-2, +3"), "\"'-2, +3\"");
+        assert_eq!(csv_field("1-2"), "1-2");
+
+        let markdown = markdown(&courses);
+        assert!(markdown.contains("## HRC1 (2 questions)"));
+        assert!(markdown.contains("### 1. Is it \\*true\\*?"));
+        assert!(markdown.contains("- [x] **Yes, \"always\"**"));
+        assert!(markdown.contains("- [ ] ~~\\<No\\>~~"));
+        assert!(markdown.contains("- [ ] Maybe _(unknown)_"));
+
+        let html = html(&courses);
+        assert!(html.contains("<li class=\"correct\">Yes, &quot;always&quot;</li>"));
+        assert!(html.contains("<li class=\"eliminated\">&lt;No&gt;</li>"));
+
+        assert!(render(Path::new("guide.txt"), &courses).is_err());
+        assert_eq!(render(Path::new("guide.CSV"), &courses).unwrap(), csv);
+    }
+}
@@ -1,48 +1,558 @@
 use std::collections::HashMap;
-use std::fs::File;
-use std::io::{BufRead, BufReader};
-use std::mem::MaybeUninit;
-use std::sync::Once;
-
-pub fn env_get(key: &str) -> Option<&'static String> {
-    load_env().get(key)
-}
-
-fn load_env() -> &'static HashMap<String, String> {
-    static mut SINGLETON: MaybeUninit<HashMap<String, String>> = MaybeUninit::uninit();
-    static ONCE: Once = Once::new();
-    unsafe {
-        ONCE.call_once(|| {
-            let file = File::open(".env").unwrap_or_else(|_| {
-                panic!("unable to find .env file");
-            });
-            let lines = BufReader::new(file).lines();
-            let mut vars: HashMap<String, String> = HashMap::new();
-            lines.for_each(|line| {
-                let str = line.unwrap_or("".to_string());
-                if !str.starts_with("#") && !str.is_empty() {
-                    let (key, value) = split_by(str, '=');
-                    vars.insert(key, value);
+use std::env;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::sync::Mutex;
+use std::time::SystemTime;
+
+use percent_encoding::percent_decode_str;
+
+/// 读取.env格式的文件，返回其中所有的键值对
+pub fn load_env_file(path: &Path) -> io::Result<HashMap<String, String>> {
+    let content = fs::read_to_string(path)?;
+    parse_env(&content).map_err(|error| io::Error::new(io::ErrorKind::InvalidData, error))
+}
+
+/// 解析.env格式的文本，支持常见的dotenv写法：
+/// - 空行和#开头的注释行，以及未加引号的值后面的` # 注释`
+/// - `export KEY=value`
+/// - 键、等号和值两侧的空白
+/// - 单引号中的值原样保留，双引号中的值支持`\n`、`\"`等转义
+/// - 加引号的值可以跨越多行
+/// - 未加单引号的值中的`${VAR}`和`$VAR`会被替换为之前定义的值或同名环境变量
+pub fn parse_env(content: &str) -> Result<HashMap<String, String>, String> {
+    let mut vars: HashMap<String, String> = HashMap::new();
+    let mut lines = content.lines().enumerate();
+    while let Some((index, line)) = lines.next() {
+        let line_number = index + 1;
+        let trimmed = line.trim();
+        if trimmed.is_empty() || trimmed.starts_with('#') {
+            continue;
+        }
+        let trimmed = trimmed
+            .strip_prefix("export ")
+            .map(|s| s.trim_start())
+            .unwrap_or(trimmed);
+        let (key, value) = match trimmed.split_once('=') {
+            Some((key, value)) => (key.trim(), value.trim_start()),
+            None => return Err(format!("line {}: expected KEY=VALUE", line_number)),
+        };
+        if key.is_empty() || key.contains(char::is_whitespace) {
+            return Err(format!("line {}: invalid key [{}]", line_number, key));
+        }
+
+        let value = match value.chars().next() {
+            Some(quote) if quote == '"' || quote == '\'' => {
+                // 找到闭合的引号为止，必要时继续读取后面的行
+                let mut raw = value[1..].to_string();
+                let end = loop {
+                    if let Some(end) = closing_quote(&raw, quote) {
+                        break end;
+                    }
+                    match lines.next() {
+                        Some((_, next)) => {
+                            raw.push('\n');
+                            raw.push_str(next);
+                        }
+                        None => {
+                            return Err(format!(
+                                "line {}: unterminated quoted value for [{}]",
+                                line_number, key
+                            ))
+                        }
+                    }
+                };
+                let rest = raw[end + 1..].trim();
+                if !rest.is_empty() && !rest.starts_with('#') {
+                    return Err(format!(
+                        "line {}: unexpected characters after quoted value for [{}]",
+                        line_number, key
+                    ));
                 }
-            });
-            SINGLETON.write(vars);
-        });
-        &SINGLETON.assume_init_ref()
+                let inner = &raw[..end];
+                if quote == '\'' {
+                    inner.to_string()
+                } else {
+                    unescape_and_expand(inner, &vars)
+                }
+            }
+            _ => {
+                let value = strip_inline_comment(value).trim_end();
+                expand(value, &vars)
+            }
+        };
+        vars.insert(key.to_string(), value);
+    }
+    Ok(vars)
+}
+
+/// 将键值对写入.env格式的文件：已有的同名键（包括跨越多行的值）被替换，其余的追加到末尾，
+/// 文件中的其他内容和注释保持不变
+pub fn update_env_file(path: &Path, pairs: &[(&str, &str)]) -> io::Result<()> {
+    let content = match fs::read_to_string(path) {
+        Ok(content) => content,
+        Err(error) if error.kind() == io::ErrorKind::NotFound => String::new(),
+        Err(error) => return Err(error),
+    };
+    let mut written = Vec::new();
+    let mut output = Vec::new();
+    let mut lines = content.lines();
+    while let Some(line) = lines.next() {
+        let trimmed = line.trim();
+        let assignment = trimmed
+            .strip_prefix("export ")
+            .unwrap_or(trimmed)
+            .split_once('=')
+            .filter(|_| !trimmed.starts_with('#'));
+        let pair = assignment.and_then(|(key, _)| pairs.iter().find(|(k, _)| *k == key.trim()));
+        match (assignment, pair) {
+            (Some((_, value)), Some((key, new_value))) => {
+                // 跳过旧值剩余的行
+                let value = value.trim_start();
+                if let Some(quote) = value.chars().next().filter(|c| *c == '"' || *c == '\'') {
+                    let mut raw = value[1..].to_string();
+                    while closing_quote(&raw, quote).is_none() {
+                        match lines.next() {
+                            Some(next) => {
+                                raw.push('\n');
+                                raw.push_str(next);
+                            }
+                            None => break,
+                        }
+                    }
+                }
+                if !written.contains(key) {
+                    output.push(format!("{}={}", key, quote_env_value(new_value)));
+                    written.push(*key);
+                }
+            }
+            _ => output.push(line.to_string()),
+        }
+    }
+    pairs
+        .iter()
+        .filter(|(key, _)| !written.contains(key))
+        .for_each(|(key, value)| output.push(format!("{}={}", key, quote_env_value(value))));
+    fs::write(path, output.join("\n") + "\n")
+}
+
+/// 将值用双引号括起来，使parse_env读回的值与原值相同
+fn quote_env_value(value: &str) -> String {
+    let mut result = String::from('"');
+    for c in value.chars() {
+        match c {
+            '\\' | '"' | '$' => {
+                result.push('\\');
+                result.push(c);
+            }
+            '\n' => result.push_str("\\n"),
+            _ => result.push(c),
+        }
+    }
+    result.push('"');
+    result
+}
+
+/// 返回未被反斜杠转义的闭合引号的位置
+fn closing_quote(str: &str, quote: char) -> Option<usize> {
+    let mut escaped = false;
+    for (index, c) in str.char_indices() {
+        if escaped {
+            escaped = false;
+        } else if c == '\\' && quote == '"' {
+            escaped = true;
+        } else if c == quote {
+            return Some(index);
+        }
+    }
+    None
+}
+
+/// 去掉未加引号的值后面以空白加#开头的注释
+fn strip_inline_comment(value: &str) -> &str {
+    let mut previous_whitespace = true;
+    for (index, c) in value.char_indices() {
+        if c == '#' && previous_whitespace {
+            return &value[..index];
+        }
+        previous_whitespace = c.is_whitespace();
     }
+    value
 }
 
-fn split_by(str: String, delimit: char) -> (String, String) {
+/// 处理双引号中的转义字符，同时替换其中的变量
+fn unescape_and_expand(value: &str, vars: &HashMap<String, String>) -> String {
+    let mut result = String::new();
+    let mut chars = value.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => match chars.next() {
+                Some('n') => result.push('\n'),
+                Some('r') => result.push('\r'),
+                Some('t') => result.push('\t'),
+                Some(other) => result.push(other),
+                None => result.push('\\'),
+            },
+            '$' => result.push_str(&variable(&mut chars, vars)),
+            _ => result.push(c),
+        }
+    }
+    result
+}
+
+/// 替换未加引号的值中的变量
+fn expand(value: &str, vars: &HashMap<String, String>) -> String {
+    let mut result = String::new();
+    let mut chars = value.chars().peekable();
+    while let Some(c) = chars.next() {
+        if c == '$' {
+            result.push_str(&variable(&mut chars, vars));
+        } else {
+            result.push(c);
+        }
+    }
+    result
+}
+
+/// 读取`$`之后的变量名（`{VAR}`或`VAR`）并返回其值，未定义的变量替换为空字符串
+fn variable<I: Iterator<Item = char>>(
+    chars: &mut std::iter::Peekable<I>,
+    vars: &HashMap<String, String>,
+) -> String {
+    let mut name = String::new();
+    if chars.peek() == Some(&'{') {
+        chars.next();
+        for c in chars.by_ref() {
+            if c == '}' {
+                break;
+            }
+            name.push(c);
+        }
+    } else {
+        while let Some(c) = chars.peek() {
+            if c.is_ascii_alphanumeric() || *c == '_' {
+                name.push(*c);
+                chars.next();
+            } else {
+                break;
+            }
+        }
+        if name.is_empty() {
+            return "$".to_string();
+        }
+    }
+    vars.get(&name)
+        .cloned()
+        .or_else(|| env::var(&name).ok())
+        .unwrap_or_default()
+}
+
+fn split_by(str: &str, delimit: char) -> (String, String) {
     let split_index = str.find(delimit).unwrap_or(str.len());
     let (key, value) = str.split_at(split_index);
     let real_value: String = value.chars().skip(1).collect();
-    (key.to_string(), real_value.to_string())
+    (key.to_string(), real_value)
 }
 
-pub fn cookies(str: &String) -> HashMap<String, String> {
-    let mut result: HashMap<String, String> = HashMap::new();
-    str.split(";").into_iter().for_each(|key_value| {
-        let (key, value) = split_by(key_value.trim().to_string(), '=');
-        result.insert(key, value);
-    });
-    result
+/// 将Cookie请求头解析为name到解码后的value，同名的cookie取第一个
+pub fn cookies(str: &str) -> HashMap<String, String> {
+    let jar = CookieJar::from_header(str);
+    let entries = jar.entries.lock().expect("cookie jar poisoned");
+    entries
+        .iter()
+        .map(|(name, value)| (name.clone(), decode_cookie_value(value)))
+        .collect()
+}
+
+/// cookie、csrf token等凭据，打印时不显示内容，避免出现在日志和错误信息中
+#[derive(Clone, Default, PartialEq, Eq)]
+pub struct Secret(String);
+
+impl Secret {
+    pub fn new(value: impl Into<String>) -> Secret {
+        Secret(value.into())
+    }
+
+    /// 凭据的原始内容，只在发送请求或写入配置文件时使用
+    pub fn expose(&self) -> &str {
+        &self.0
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+}
+
+impl From<String> for Secret {
+    fn from(value: String) -> Secret {
+        Secret(value)
+    }
+}
+
+impl fmt::Debug for Secret {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", REDACTED)
+    }
+}
+
+impl fmt::Display for Secret {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", REDACTED)
+    }
+}
+
+const REDACTED: &str = "[redacted]";
+
+/// 短于此长度的值不当作凭据隐藏，如cookie中的true、en等，避免把普通文字也替换掉
+const MIN_SECRET_LEN: usize = 6;
+
+/// 将文本中出现的凭据替换为[redacted]
+pub fn redact(text: &str, secrets: &[&str]) -> String {
+    let mut secrets = secrets
+        .iter()
+        .filter(|secret| secret.len() >= MIN_SECRET_LEN)
+        .collect::<Vec<&&str>>();
+    // 先替换长的，避免一个凭据是另一个凭据的一部分时只替换了一半
+    secrets.sort_by_key(|secret| std::cmp::Reverse(secret.len()));
+    secrets.into_iter().fold(text.to_string(), |text, secret| {
+        text.replace(*secret, REDACTED)
+    })
+}
+
+/// 请求时使用的cookie，以配置中的Cookie请求头为初始值，并随响应中的Set-Cookie更新
+///
+/// 只记录name和原始value，发送时按原样拼接；同名的cookie只保留第一个，
+/// 与服务器读取Cookie请求头时的行为一致
+#[derive(Default)]
+pub struct CookieJar {
+    entries: Mutex<Vec<(String, String)>>,
+}
+
+impl CookieJar {
+    pub fn from_header(header: &str) -> CookieJar {
+        let mut entries: Vec<(String, String)> = Vec::new();
+        header
+            .split(';')
+            .map(|pair| pair.trim())
+            .filter(|pair| !pair.is_empty())
+            .for_each(|pair| {
+                let (name, value) = split_by(pair, '=');
+                let name = name.trim().to_string();
+                if !entries.iter().any(|(n, _)| *n == name) {
+                    entries.push((name, value.trim().to_string()));
+                }
+            });
+        CookieJar {
+            entries: Mutex::new(entries),
+        }
+    }
+
+    /// 拼接为Cookie请求头
+    pub fn header(&self) -> String {
+        self.entries
+            .lock()
+            .expect("cookie jar poisoned")
+            .iter()
+            .map(|(name, value)| format!("{}={}", name, value))
+            .collect::<Vec<String>>()
+            .join("; ")
+    }
+
+    /// 解码后的cookie值，如PSA_CPNT_REV_DATE中的%2f会被还原为/
+    pub fn get(&self, name: &str) -> Option<String> {
+        self.entries
+            .lock()
+            .expect("cookie jar poisoned")
+            .iter()
+            .find(|(n, _)| n == name)
+            .map(|(_, value)| decode_cookie_value(value))
+    }
+
+    /// 将文本中出现的cookie值（包括解码后的值）替换为[redacted]
+    pub fn redact(&self, text: &str) -> String {
+        let entries = self.entries.lock().expect("cookie jar poisoned");
+        let values = entries
+            .iter()
+            .flat_map(|(_, value)| [value.clone(), decode_cookie_value(value)])
+            .collect::<Vec<String>>();
+        redact(
+            text,
+            &values.iter().map(|v| v.as_str()).collect::<Vec<&str>>(),
+        )
+    }
+
+    /// 根据一个Set-Cookie响应头更新或删除cookie，返回cookie是否有变化
+    pub fn absorb(&self, set_cookie: &str) -> bool {
+        let mut parts = set_cookie.split(';');
+        let (name, value) = split_by(parts.next().unwrap_or("").trim(), '=');
+        let name = name.trim().to_string();
+        if name.is_empty() {
+            return false;
+        }
+        let value = value.trim().to_string();
+        let expired = parts.any(|attribute| {
+            let (key, attr) = split_by(attribute.trim(), '=');
+            if key.eq_ignore_ascii_case("max-age") {
+                attr.trim().parse::<i64>().is_ok_and(|age| age <= 0)
+            } else if key.eq_ignore_ascii_case("expires") {
+                httpdate::parse_http_date(attr.trim()).is_ok_and(|time| time <= SystemTime::now())
+            } else {
+                false
+            }
+        });
+
+        let mut entries = self.entries.lock().expect("cookie jar poisoned");
+        let position = entries.iter().position(|(n, _)| *n == name);
+        match (position, expired) {
+            (Some(index), true) => {
+                entries.remove(index);
+                true
+            }
+            (None, true) => false,
+            (Some(index), false) => {
+                let changed = entries[index].1 != value;
+                entries[index].1 = value;
+                changed
+            }
+            (None, false) => {
+                entries.push((name, value));
+                true
+            }
+        }
+    }
+}
+
+/// 只显示cookie的name，value可能是登录凭据
+impl fmt::Debug for CookieJar {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let entries = self.entries.lock().expect("cookie jar poisoned");
+        f.debug_map()
+            .entries(entries.iter().map(|(name, _)| (name, REDACTED)))
+            .finish()
+    }
+}
+
+fn decode_cookie_value(value: &str) -> String {
+    let value = value.trim_matches('"');
+    percent_decode_str(value).decode_utf8_lossy().to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{cookies, parse_env, redact, update_env_file, CookieJar, Secret};
+
+    const FIXTURE: &str = include_str!("../mock/sample.env");
+
+    #[test]
+    fn parse_env_fixture_test() {
+        let vars = parse_env(FIXTURE).unwrap();
+        let get = |key: &str| vars.get(key).map(|v| v.as_str());
+        assert_eq!(get("QUIZ_ID"), Some("82f931a4-b474-4da3-9e6f-a081183e305a"));
+        assert_eq!(
+            get("COOKIE"),
+            Some("JSESSIONID=C6588F49; PSA_STUD_CPNT_ID=35792014; PSA_STUD_CPNT_MOD_ID=653819")
+        );
+        assert_eq!(get("CSRF_TOKEN"), Some("YAM7-FSWU-Y766"));
+        assert_eq!(get("EXPORTED"), Some("value"));
+        assert_eq!(get("SPACED"), Some("spaced value"));
+        assert_eq!(get("COMMENTED"), Some("value"));
+        assert_eq!(get("HASH_IN_VALUE"), Some("a#b"));
+        assert_eq!(get("QUOTED_HASH"), Some("a # b"));
+        assert_eq!(get("SINGLE"), Some("literal ${QUIZ_ID} \\n"));
+        assert_eq!(get("ESCAPED"), Some("line1\nline2 \"quoted\" $HOME"));
+        assert_eq!(get("MULTILINE"), Some("first line\nsecond line"));
+        assert_eq!(
+            get("INTERPOLATED"),
+            Some("quiz/82f931a4-b474-4da3-9e6f-a081183e305a/iteration")
+        );
+        assert_eq!(get("BARE_INTERPOLATED"), Some("YAM7-FSWU-Y766!"));
+        assert_eq!(get("UNDEFINED"), Some("[]"));
+        assert_eq!(get("EMPTY"), Some(""));
+        assert_eq!(vars.len(), 15);
+    }
+
+    #[test]
+    fn update_env_file_test() {
+        let path = std::env::temp_dir().join(format!("wbl-robot-{}.env", std::process::id()));
+        std::fs::write(
+            &path,
+            "# comment\nQUIZ_ID=old\nCOOKIE=\"multi\nline\"\nexport CSRF_TOKEN=old\nOTHER=kept\n",
+        )
+        .unwrap();
+        update_env_file(
+            &path,
+            &[
+                ("QUIZ_ID", "new"),
+                ("COOKIE", "a=b; c=\"$d\""),
+                ("CSRF_TOKEN", "token"),
+                ("PSA_STUD_CPNT_ID", "1"),
+            ],
+        )
+        .unwrap();
+        let content = std::fs::read_to_string(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert!(content.starts_with("# comment\n"));
+        let vars = parse_env(&content).unwrap();
+        let get = |key: &str| vars.get(key).map(|v| v.as_str());
+        assert_eq!(get("QUIZ_ID"), Some("new"));
+        assert_eq!(get("COOKIE"), Some("a=b; c=\"$d\""));
+        assert_eq!(get("CSRF_TOKEN"), Some("token"));
+        assert_eq!(get("OTHER"), Some("kept"));
+        assert_eq!(get("PSA_STUD_CPNT_ID"), Some("1"));
+        assert_eq!(vars.len(), 5);
+    }
+
+    #[test]
+    fn parse_env_error_test() {
+        assert!(parse_env("KEY=\"never closed\nNEXT=1").is_err());
+        assert!(parse_env("KEY='a' trailing").is_err());
+        assert!(parse_env("NO_EQUALS_SIGN").is_err());
+        assert!(parse_env("BAD KEY=1").is_err());
+    }
+
+    #[test]
+    fn cookie_jar_test() {
+        let jar = CookieJar::from_header(
+            "JSESSIONID=C6588F49; route=a685; PSA_CPNT_REV_DATE=04%2f13%2f2020%2008%3a01%3a00; JSESSIONID=dup",
+        );
+        assert_eq!(jar.get("JSESSIONID"), Some("C6588F49".to_string()));
+        assert_eq!(
+            jar.get("PSA_CPNT_REV_DATE"),
+            Some("04/13/2020 08:01:00".to_string())
+        );
+        assert!(jar.absorb("JSESSIONID=NEW; Path=/learning; Secure; HttpOnly"));
+        assert!(!jar.absorb("JSESSIONID=NEW; Path=/learning"));
+        assert!(jar.absorb("AKAMAI_AUTH_COOKIE=~expires=1666317383~md5=26c1; Path=/"));
+        assert!(jar.absorb("route=gone; Max-Age=0"));
+        assert!(!jar.absorb("OLD=1; Expires=Thu, 01 Jan 1970 00:00:00 GMT"));
+        assert!(jar.absorb("SKIP=1; Expires=Fri, 01 Jan 2100 00:00:00 GMT"));
+        assert_eq!(
+            jar.header(),
+            "JSESSIONID=NEW; PSA_CPNT_REV_DATE=04%2f13%2f2020%2008%3a01%3a00; AKAMAI_AUTH_COOKIE=~expires=1666317383~md5=26c1; SKIP=1"
+        );
+        assert!(jar.absorb("SKIP=1; Max-Age=-1"));
+        assert_eq!(jar.get("SKIP"), None);
+        assert_eq!(cookies("A=1; B=%20x").get("B"), Some(&" x".to_string()));
+    }
+
+    #[test]
+    fn redact_test() {
+        let secret = Secret::new("C6588F49D2");
+        assert_eq!(format!("{} {:?}", secret, secret), "[redacted] [redacted]");
+        assert_eq!(secret.expose(), "C6588F49D2");
+
+        assert_eq!(
+            redact("token YAM7-FSWU-Y766 is true", &["YAM7-FSWU-Y766", "true"]),
+            "token [redacted] is true"
+        );
+
+        let jar = CookieJar::from_header("JSESSIONID=C6588F49D2; PSA_CPNT_REV_DATE=04%2f13%2f2020");
+        assert_eq!(
+            jar.redact("session C6588F49D2 since 04/13/2020"),
+            "session [redacted] since [redacted]"
+        );
+        assert!(!format!("{:?}", jar).contains("C6588F49D2"));
+    }
 }
@@ -1,102 +1,191 @@
 use std::error::Error;
+use std::path::PathBuf;
 
-use mongodb::{options::ClientOptions, Client};
+use clap::{Parser, Subcommand};
 
-use crate::global::{GlobalData, MemoryStoreQuestion};
-use crate::request::{iteration_request, save_request, start_request, submit_request, SaveBody};
+use crate::config::{Config, ConfigArgs};
+use crate::global::GlobalData;
+use crate::merge::MergePolicy;
 use crate::response::{CustomResponse, IterationData, StartData};
 use crate::url::{iteration_url, start_url};
 
+mod bank;
+mod command;
+mod config;
+mod credentials;
+mod diff;
+mod evidence;
 mod global;
+mod guide;
+mod merge;
+mod mock_server;
+mod planner;
 mod request;
 mod response;
+mod schema;
+mod session;
+mod solver;
+mod store;
 mod url;
+mod verify;
 
-#[tokio::main]
-async fn main() -> Result<(), Box<dyn Error>> {
-    let mut global_data = GlobalData::from_env();
-
-    let mut client_options = ClientOptions::parse("mongodb://localhost:27017").await?;
-    client_options.app_name = Some("wbl robot".to_string());
-    let db_client = Client::with_options(client_options)?;
-    let db = db_client.database("wbl_robot");
-
-    let questions_collection = db.collection::<MemoryStoreQuestion>("questions");
-
-    let client: reqwest::Client = reqwest::Client::new();
+/// Answer WBL quizzes automatically and manage the answer bank
+#[derive(Parser)]
+#[command(name = "wbl-robot", version)]
+struct Cli {
+    #[command(flatten)]
+    config: ConfigArgs,
+    /// Defaults to `run` when omitted
+    #[command(subcommand)]
+    command: Option<Command>,
+}
 
-    loop {
-        /*iteration*/
-        let iteration_result = iteration_request(&client, &global_data).await?;
-        let student_assessment_iteration = iteration_result
-            .clone()
-            .student_assessment_iteration
-            .expect("unable to get [student_assessment_iteration]");
-        global_data.set_student_guid(student_assessment_iteration.student_assessment_sys_guid);
-        student_assessment_iteration.questions.iter().for_each(|q| {
-            global_data.insert_question(q.student_assessment_question_sys_guid.clone());
-        });
-        println!(
-            "\x1b[30mget {} question\x1b[0m",
-            global_data.question_count()
-        );
+#[derive(Subcommand)]
+enum Command {
+    /// Keep answering the configured quiz and remember what was learned
+    Run {
+        /// Move inconsistent questions into QUARANTINE_PATH before answering, like
+        /// verify --quarantine; otherwise they are only reported and answered as unknown
+        #[arg(long)]
+        quarantine: bool,
+    },
+    /// Import a bank file (.json from mongoexport --jsonArray, or a .zip of it) into the store
+    ImportBank {
+        /// The bank file to import, e.g. bank/questions-HRC8045921.zip
+        file: PathBuf,
+    },
+    /// Export every stored question into a bank file (.json or .zip)
+    ExportBank {
+        /// The bank file to write, an existing file is overwritten; with --by-course the
+        /// directory to write the banks into, e.g. bank
+        file: PathBuf,
+        /// Write one questions-<course id>.zip per course, like the banks in bank/
+        #[arg(long)]
+        by_course: bool,
+    },
+    /// Write the banks as a study guide listing the known correct, eliminated and unknown
+    /// choices of each question, grouped per course; the format follows the extension
+    /// of the output file: .csv, .md or .html
+    ExportGuide {
+        /// The guide file to write, an existing file is overwritten
+        output: PathBuf,
+        /// Bank files or directories of bank files
+        #[arg(default_value = "bank")]
+        inputs: Vec<PathBuf>,
+    },
+    /// Show how many stored questions already have known answers
+    Stats,
+    /// Show what is known about a stored question and the attempts it was deduced from
+    Explain {
+        /// The questionID of the question
+        useful_id: String,
+        /// The locale of the stored question, defaults to LOCALE
+        #[arg(long)]
+        locale: Option<String>,
+    },
+    /// Replay every recorded attempt with the current deduction rules and write the result
+    /// into a bank file, without touching the store
+    RebuildBank {
+        /// The bank file to write, an existing file is overwritten
+        file: PathBuf,
+    },
+    /// Combine the questions_correct of every recorded attempt to deduce correct and wrong
+    /// choices that no single attempt reveals, and save them into the store
+    Solve,
+    /// Check that every stored question is consistent, e.g. no choice is both correct and
+    /// error and not every choice is eliminated
+    Verify {
+        /// Move inconsistent questions into QUARANTINE_PATH and keep only the questions
+        /// themselves in the store, so they are answered again
+        #[arg(long)]
+        quarantine: bool,
+    },
+    /// Check that a bank file can be read and has no duplicate or contradictory questions
+    VerifyBank {
+        /// The bank file to check
+        file: PathBuf,
+    },
+    /// Merge several bank files into one, reporting questions the banks disagree on
+    MergeBanks {
+        /// The bank file to write, it may also be one of the inputs
+        output: PathBuf,
+        /// The bank files to merge
+        #[arg(required = true)]
+        inputs: Vec<PathBuf>,
+        /// What to do when banks disagree on the correct choices of a question
+        #[arg(long, value_enum, default_value_t = MergePolicy::Evidence)]
+        policy: MergePolicy,
+    },
+    /// Show what was learned between two bank files, or between a bank file and the store:
+    /// new questions, newly known correct choices, new eliminations and regressions
+    DiffBanks {
+        /// The earlier bank file
+        old: PathBuf,
+        /// The later bank file, defaults to the questions in the store
+        new: Option<PathBuf>,
+    },
+    /// Write QUIZ_ID, COOKIE and CSRF_TOKEN into the config file from a quiz request
+    /// copied with "Copy as cURL" or from an exported .har file
+    ImportCredentials {
+        /// A file holding the cURL command or the HAR, or - to read it from stdin
+        source: PathBuf,
+    },
+    /// Serve a local stand-in for the quiz api, using the known answers of a bank file
+    ServeMock {
+        /// The bank file to take questions from
+        bank: PathBuf,
+        /// The port to listen on
+        #[arg(long, default_value_t = 8080)]
+        port: u16,
+        /// How many questions each iteration has
+        #[arg(long, default_value_t = 10)]
+        questions: usize,
+    },
+}
 
-        /*start*/
-        println!("\x1b[30mrequest question choices\x1b[0m");
-        let start_result =
-            start_request(&client, global_data.first_question(), &global_data).await?;
-        global_data.set_useful_id(global_data.first_question(), start_result.question_id);
-        global_data.set_format(global_data.first_question(), start_result.format);
-        global_data.set_choices(global_data.first_question(), start_result.answer_choices);
-        global_data
-            .update_from_db(&questions_collection, global_data.first_question())
-            .await?;
+#[tokio::main]
+async fn main() -> Result<(), Box<dyn Error>> {
+    let cli = Cli::parse();
+    let command = cli.command.unwrap_or(Command::Run { quarantine: false });
+    let require_quiz = matches!(command, Command::Run { .. });
+    let load_config = || {
+        Config::load(&cli.config, require_quiz).unwrap_or_else(|error| {
+            eprint!("{}", error);
+            std::process::exit(1);
+        })
+    };
 
-        /*save and next*/
-        for i in 0..global_data.question_count() - 1 {
-            let current = global_data.get_question_id(i);
-            let next = global_data.get_question_id(i + 1);
-            global_data.select_choices(current.clone(), true);
-            let body = SaveBody::from(global_data.chosen_choices(current.clone()), next.clone());
-            println!("\x1b[30msend a question answer\x1b[0m");
-            let save_result = save_request(&client, current.clone(), &global_data, &body)
-                .await
-                .expect("fail to get save request response");
-            global_data.set_useful_id(next.clone(), save_result.question_id);
-            global_data.set_format(next.clone(), save_result.format);
-            global_data.set_choices(next.clone(), save_result.answer_choices);
-            global_data
-                .update_from_db(&questions_collection, next.clone())
-                .await?;
+    match command {
+        Command::Run { quarantine } => command::run(&cli.config, &load_config(), quarantine).await,
+        Command::ImportBank { file } => command::import_bank(&load_config(), &file).await,
+        Command::ExportBank { file, by_course } => {
+            command::export_bank(&load_config(), &file, by_course).await
         }
-
-        /*submit*/
-        let last_question_id = global_data.last_question();
-        global_data.select_choices(last_question_id.clone(), false);
-        let body = SaveBody::from(
-            global_data.chosen_choices(last_question_id.clone()),
-            last_question_id.clone(),
-        );
-        println!("\x1b[30msend a question answer\x1b[0m");
-        let submit_result =
-            submit_request(&client, last_question_id.clone(), &global_data, &body).await?;
-        println!(
-            "get score({}), correct({}), already know correct({})",
-            submit_result.score,
-            submit_result.questions_correct,
-            global_data.get_min_correct()
-        );
-
-        if submit_result.score == 100 {
-            println!("\x1b[36mtry to remember correct choices\x1b[0m");
-            global_data.remember_correct();
-        } else if global_data.get_min_correct() == submit_result.questions_correct {
-            println!("\x1b[36mtry to remember error choices\x1b[0m");
-            global_data.remember_error();
+        Command::ExportGuide { output, inputs } => command::export_guide(&output, &inputs),
+        Command::Stats => command::stats(&load_config()).await,
+        Command::Explain { useful_id, locale } => {
+            let config = load_config();
+            let locale = locale.unwrap_or_else(|| config.locale.clone());
+            command::explain(&config, &useful_id, &locale).await
         }
-
-        global_data.store_into_db(&questions_collection).await?;
-
-        global_data.clear_question();
+        Command::RebuildBank { file } => command::rebuild_bank(&load_config(), &file).await,
+        Command::Solve => command::solve(&load_config()).await,
+        Command::Verify { quarantine } => command::verify(&load_config(), quarantine).await,
+        Command::VerifyBank { file } => command::verify_bank(&file),
+        Command::MergeBanks {
+            output,
+            inputs,
+            policy,
+        } => command::merge_banks(&output, &inputs, policy),
+        Command::DiffBanks { old, new } => match new {
+            Some(new) => command::diff_banks(&old, &new),
+            None => command::diff_store(&load_config(), &old).await,
+        },
+        Command::ImportCredentials { source } => command::import_credentials(&cli.config, &source),
+        Command::ServeMock {
+            bank,
+            port,
+            questions,
+        } => mock_server::serve(&bank, port, questions).await,
     }
 }
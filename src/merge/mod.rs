@@ -0,0 +1,257 @@
+use std::fmt;
+use std::time::SystemTime;
+
+use clap::ValueEnum;
+
+use crate::global::{compare_multiple_choices, MemoryStoreQuestion};
+use crate::response::{AnswerChoice, QuestionFormat};
+
+/// 多个题库对同一题目的正确答案有冲突时的处理方式
+#[derive(Debug, Clone, Copy, PartialEq, ValueEnum)]
+pub enum MergePolicy {
+    /// Keep the answer of the most recently modified bank
+    Newer,
+    /// Keep the answer of the bank that eliminated the most wrong choices
+    Evidence,
+    /// Stop without writing anything
+    Fail,
+}
+
+/// 参与合并的一个题库
+pub struct Bank {
+    pub name: String,
+    pub modified: SystemTime,
+    pub questions: Vec<MemoryStoreQuestion>,
+}
+
+/// 同一题目在不同题库中的记录互相矛盾
+#[derive(Debug, PartialEq)]
+pub struct Conflict {
+    pub useful_id: String,
+    pub locale: String,
+    pub reason: String,
+}
+
+impl fmt::Display for Conflict {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "[{}] in {}: {}",
+            self.useful_id, self.locale, self.reason
+        )
+    }
+}
+
+/// 某个题库中的一条记录
+struct Record<'a> {
+    bank: &'a Bank,
+    question: &'a MemoryStoreQuestion,
+}
+
+impl Record<'_> {
+    /// 记录中排除过的错误答案数量，越多说明这份记录答过的次数越多
+    fn evidence(&self) -> usize {
+        self.question.error_choices.len() + self.question.multiple_error_choices.len()
+    }
+
+    /// 记录中的错误答案是否否定了这组正确选项
+    fn contradicts(&self, correct: &[AnswerChoice]) -> bool {
+        match self.question.format {
+            QuestionFormat::MultiChoiceSingleAnswer => correct
+                .iter()
+                .any(|c| self.question.error_choices.iter().any(|e| e.same_choice(c))),
+            QuestionFormat::MultiChoiceMultipleAnswer => self
+                .question
+                .multiple_error_choices
+                .iter()
+                .any(|m| compare_multiple_choices(m, correct)),
+        }
+    }
+}
+
+/// 按useful_id和locale合并多个题库：错误答案取并集，正确答案有冲突时按policy选择，
+/// 返回合并后的题目和发现的所有冲突；policy为Fail时只要有冲突就不返回题目
+pub fn merge_banks(
+    banks: &[Bank],
+    policy: MergePolicy,
+) -> (Option<Vec<MemoryStoreQuestion>>, Vec<Conflict>) {
+    let mut groups: Vec<Vec<Record>> = Vec::new();
+    for bank in banks {
+        for question in bank.questions.iter() {
+            let group = groups.iter_mut().find(|g| {
+                g[0].question.useful_id == question.useful_id
+                    && g[0].question.locale == question.locale
+            });
+            let record = Record { bank, question };
+            match group {
+                Some(group) => group.push(record),
+                None => groups.push(vec![record]),
+            }
+        }
+    }
+
+    let mut conflicts = Vec::new();
+    let merged = groups
+        .iter()
+        .map(|group| merge_group(group, policy, &mut conflicts))
+        .collect();
+    if policy == MergePolicy::Fail && !conflicts.is_empty() {
+        (None, conflicts)
+    } else {
+        (Some(merged), conflicts)
+    }
+}
+
+fn merge_group(
+    group: &[Record],
+    policy: MergePolicy,
+    conflicts: &mut Vec<Conflict>,
+) -> MemoryStoreQuestion {
+    let newest = group
+        .iter()
+        .max_by_key(|r| r.bank.modified)
+        .expect("group is never empty");
+    let mut merged = newest.question.clone();
+    merged.error_choices = Vec::new();
+    merged.multiple_error_choices = Vec::new();
+    group
+        .iter()
+        .for_each(|r| merged.merge_knowledge(r.question));
+    // 正确答案在检查冲突后按policy选择
+    merged.correct_choices = Vec::new();
+
+    let mut conflict = |reason: String| {
+        conflicts.push(Conflict {
+            useful_id: merged.useful_id.clone(),
+            locale: merged.locale.clone(),
+            reason,
+        })
+    };
+    let answered = group
+        .iter()
+        .filter(|r| !r.question.correct_choices.is_empty())
+        .collect::<Vec<&Record>>();
+    for (i, a) in answered.iter().enumerate() {
+        for b in answered[i + 1..].iter() {
+            if !compare_multiple_choices(&a.question.correct_choices, &b.question.correct_choices) {
+                conflict(format!(
+                    "{} and {} have different correct choices",
+                    a.bank.name, b.bank.name
+                ));
+            }
+        }
+        for other in group.iter() {
+            if other.contradicts(&a.question.correct_choices) {
+                conflict(format!(
+                    "the correct choices in {} are marked as an error in {}",
+                    a.bank.name, other.bank.name
+                ));
+            }
+        }
+    }
+
+    let winner = match policy {
+        MergePolicy::Newer | MergePolicy::Fail => answered.iter().max_by_key(|r| r.bank.modified),
+        MergePolicy::Evidence => answered
+            .iter()
+            .max_by_key(|r| (r.evidence(), r.bank.modified)),
+    };
+    if let Some(winner) = winner {
+        let correct = winner.question.correct_choices.clone();
+        // 与选定的正确答案矛盾的错误记录一定是错的
+        merged
+            .error_choices
+            .retain(|e| !correct.iter().any(|c| c.same_choice(e)));
+        merged
+            .multiple_error_choices
+            .retain(|m| !compare_multiple_choices(m, &correct));
+        merged.correct_choices = correct;
+    }
+    merged
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, SystemTime};
+
+    use crate::global::MemoryStoreQuestion;
+    use crate::merge::{merge_banks, Bank, MergePolicy};
+    use crate::response::AnswerChoice;
+
+    fn bank(name: &str, age: u64, questions: Vec<MemoryStoreQuestion>) -> Bank {
+        Bank {
+            name: name.to_string(),
+            modified: SystemTime::UNIX_EPOCH + Duration::from_secs(1000 - age),
+            questions,
+        }
+    }
+
+    fn values(choices: &[AnswerChoice]) -> Vec<String> {
+        let mut values = choices
+            .iter()
+            .map(|c| c.choice_value.clone())
+            .collect::<Vec<String>>();
+        values.sort();
+        values
+    }
+
+    #[test]
+    fn merge_without_conflict_test() {
+        let banks = [
+            bank(
+                "a",
+                10,
+                vec![
+                    MemoryStoreQuestion::mock("q1", &["1", "2", "3", "4"]).with_errors(&["1"]),
+                    MemoryStoreQuestion::mock("q2", &["1", "2", "3", "4"]).with_correct(&["3"]),
+                ],
+            ),
+            bank(
+                "b",
+                0,
+                vec![
+                    MemoryStoreQuestion::mock("q1", &["1", "2", "3", "4"]).with_errors(&["2"]),
+                    MemoryStoreQuestion::mock("q1", &["1", "2", "3", "4"]).with_errors(&["1"]),
+                ],
+            ),
+        ];
+        let (merged, conflicts) = merge_banks(&banks, MergePolicy::Fail);
+        assert!(conflicts.is_empty());
+        let merged = merged.unwrap();
+        assert_eq!(merged.len(), 2);
+        assert_eq!(values(&merged[0].error_choices), vec!["1", "2"]);
+        assert_eq!(values(&merged[1].correct_choices), vec!["3"]);
+    }
+
+    #[test]
+    fn merge_conflict_test() {
+        let banks = [
+            bank(
+                "old",
+                10,
+                vec![MemoryStoreQuestion::mock("q1", &["1", "2", "3", "4"])
+                    .with_correct(&["1"])
+                    .with_errors(&["2", "3"])],
+            ),
+            bank(
+                "new",
+                0,
+                vec![MemoryStoreQuestion::mock("q1", &["1", "2", "3", "4"]).with_correct(&["2"])],
+            ),
+        ];
+        let (merged, conflicts) = merge_banks(&banks, MergePolicy::Fail);
+        assert!(merged.is_none());
+        // 正确答案不同，且new的正确答案在old中被记为错误
+        assert_eq!(conflicts.len(), 2);
+
+        let (merged, _) = merge_banks(&banks, MergePolicy::Newer);
+        let merged = merged.unwrap();
+        assert_eq!(values(&merged[0].correct_choices), vec!["2"]);
+        assert_eq!(values(&merged[0].error_choices), vec!["3"]);
+
+        let (merged, _) = merge_banks(&banks, MergePolicy::Evidence);
+        let merged = merged.unwrap();
+        assert_eq!(values(&merged[0].correct_choices), vec!["1"]);
+        assert_eq!(values(&merged[0].error_choices), vec!["2", "3"]);
+    }
+}
@@ -0,0 +1,277 @@
+use std::collections::HashMap;
+use std::convert::Infallible;
+use std::error::Error;
+use std::future::Future;
+use std::net::SocketAddr;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+
+use hyper::service::{make_service_fn, service_fn};
+use hyper::{Body, Method, Request, Response, Server, StatusCode};
+use rand::seq::SliceRandom;
+use rand::Rng;
+use serde::Serialize;
+
+use crate::bank::read_bank;
+use crate::global::{compare_multiple_choices, MemoryStoreQuestion};
+use crate::request::SaveBody;
+use crate::response::{
+    AnswerChoice, CustomResponse, Data, IterationData, Question, RestOperationStatusVOX, StartData,
+    StudentAssessmentIteration, SubmitData,
+};
+
+/// 模拟服务器中的一次答题
+struct MockIteration {
+    /// 本次答题的题目，studentAssessmentQuestionSysGUID 与题库中的序号
+    questions: Vec<(String, usize)>,
+    answers: HashMap<String, Vec<AnswerChoice>>,
+}
+
+struct MockState {
+    bank: Vec<MemoryStoreQuestion>,
+    question_count: usize,
+    iterations: HashMap<String, MockIteration>,
+}
+
+/// 在本地端口上模拟测试服务器的quiz接口，题目和正确答案来自题库文件中已知答案的题目
+pub async fn serve(bank: &Path, port: u16, question_count: usize) -> Result<(), Box<dyn Error>> {
+    if question_count == 0 {
+        return Err("each iteration needs at least one question".into());
+    }
+    let bank = read_bank(bank)?
+        .into_iter()
+        .filter(|q| !q.correct_choices.is_empty())
+        .collect::<Vec<MemoryStoreQuestion>>();
+    if bank.is_empty() {
+        return Err("the bank has no question with known correct choices".into());
+    }
+    let (addr, server) = bind(
+        bank,
+        SocketAddr::from(([127, 0, 0, 1], port)),
+        question_count,
+    )?;
+    println!("mock server listening on http://{}", addr);
+    println!(
+        "answer against it with: wbl-robot run --api-scheme http --api-host {} --api-prefix /",
+        addr
+    );
+    server.await?;
+    Ok(())
+}
+
+/// 绑定端口（为0时由系统分配），返回实际监听的地址和需要运行的服务
+pub(crate) fn bind(
+    bank: Vec<MemoryStoreQuestion>,
+    addr: SocketAddr,
+    question_count: usize,
+) -> Result<(SocketAddr, impl Future<Output = hyper::Result<()>>), Box<dyn Error>> {
+    let state = Arc::new(Mutex::new(MockState {
+        question_count: question_count.min(bank.len()),
+        bank,
+        iterations: HashMap::new(),
+    }));
+    let make_service = make_service_fn(move |_| {
+        let state = state.clone();
+        async move {
+            Ok::<_, Infallible>(service_fn(move |req| {
+                let state = state.clone();
+                async move { Ok::<_, Infallible>(handle(state, req).await) }
+            }))
+        }
+    });
+    let server = Server::try_bind(&addr)?.serve(make_service);
+    Ok((server.local_addr(), server))
+}
+
+async fn handle(state: Arc<Mutex<MockState>>, req: Request<Body>) -> Response<Body> {
+    let method = req.method().clone();
+    let segments = req
+        .uri()
+        .path()
+        .split('/')
+        .map(|s| s.to_string())
+        .collect::<Vec<String>>();
+    let body = match hyper::body::to_bytes(req.into_body()).await {
+        Ok(body) => body,
+        Err(error) => return error_response(StatusCode::BAD_REQUEST, error.to_string()),
+    };
+    // 只关心quiz之后的路径，前面的租户和api前缀随意
+    let path = match segments.iter().position(|s| s == "quiz") {
+        Some(index) => &segments[index + 1..],
+        None => return error_response(StatusCode::NOT_FOUND, "not a quiz url".to_string()),
+    };
+    let path = path.iter().map(|s| s.as_str()).collect::<Vec<&str>>();
+    let mut state = state.lock().expect("mock state poisoned");
+
+    match (method, path.as_slice()) {
+        (Method::GET, [quiz_id, "iteration", _locale]) => {
+            let quiz_id = quiz_id.to_string();
+            success(state.new_iteration(quiz_id))
+        }
+        (Method::GET, [_, "iteration", iteration, "question", question]) => {
+            match state.start_data(iteration, question) {
+                Some(data) => success(data),
+                None => error_response(StatusCode::NOT_FOUND, "unknown question".to_string()),
+            }
+        }
+        (Method::PUT, [_, "iteration", iteration, "question", question, action]) => {
+            let save_body = match serde_json::from_slice::<SaveBody>(&body) {
+                Ok(b) => b,
+                Err(error) => return error_response(StatusCode::BAD_REQUEST, error.to_string()),
+            };
+            let recorded = state.record_answer(iteration, question, save_body.student_answers);
+            if !recorded {
+                return error_response(StatusCode::NOT_FOUND, "unknown question".to_string());
+            }
+            match *action {
+                "saveAndNext" => {
+                    let next = save_body.next.unwrap_or_default();
+                    match state.start_data(iteration, &next) {
+                        Some(data) => success(data),
+                        None => {
+                            error_response(StatusCode::NOT_FOUND, "unknown question".to_string())
+                        }
+                    }
+                }
+                "saveAndSubmit" => success(state.submit(iteration)),
+                _ => error_response(StatusCode::NOT_FOUND, "unknown action".to_string()),
+            }
+        }
+        _ => error_response(StatusCode::NOT_FOUND, "unknown endpoint".to_string()),
+    }
+}
+
+impl MockState {
+    fn new_iteration(&mut self, quiz_id: String) -> IterationData {
+        let mut rng = rand::thread_rng();
+        let mut indexes = (0..self.bank.len()).collect::<Vec<usize>>();
+        indexes.shuffle(&mut rng);
+        let questions = indexes
+            .into_iter()
+            .take(self.question_count)
+            .map(|index| (guid(), index))
+            .collect::<Vec<(String, usize)>>();
+        let student_guid = guid();
+        let iteration = StudentAssessmentIteration {
+            question_count: questions.len(),
+            questions: questions
+                .iter()
+                .map(|(id, _)| Question {
+                    student_assessment_question_sys_guid: id.clone(),
+                })
+                .collect(),
+            student_assessment_sys_guid: student_guid.clone(),
+            quiz_id,
+            quiz_sys_guid: guid(),
+        };
+        self.iterations.insert(
+            student_guid,
+            MockIteration {
+                questions,
+                answers: HashMap::new(),
+            },
+        );
+        IterationData {
+            student_assessment_iteration: Some(iteration),
+        }
+    }
+
+    fn bank_question(&self, iteration: &str, question: &str) -> Option<&MemoryStoreQuestion> {
+        self.iterations
+            .get(iteration)?
+            .questions
+            .iter()
+            .find(|(id, _)| id == question)
+            .map(|(_, index)| &self.bank[*index])
+    }
+
+    fn start_data(&self, iteration: &str, question: &str) -> Option<StartData> {
+        let bank_question = self.bank_question(iteration, question)?;
+        let mut answer_choices = bank_question.choices.clone();
+        answer_choices
+            .iter_mut()
+            .for_each(|c| c.is_selected = false);
+        Some(StartData {
+            question_id: bank_question.useful_id.clone(),
+            answer_choices,
+            format: bank_question.format.clone(),
+            details: bank_question.details.clone(),
+        })
+    }
+
+    fn record_answer(
+        &mut self,
+        iteration: &str,
+        question: &str,
+        answers: Vec<AnswerChoice>,
+    ) -> bool {
+        if self.bank_question(iteration, question).is_none() {
+            return false;
+        }
+        self.iterations
+            .get_mut(iteration)
+            .expect("checked above")
+            .answers
+            .insert(question.to_string(), answers);
+        true
+    }
+
+    fn submit(&mut self, iteration: &str) -> SubmitData {
+        let iteration = self
+            .iterations
+            .remove(iteration)
+            .expect("iteration checked when recording the answer");
+        let questions_correct = iteration
+            .questions
+            .iter()
+            .filter(|(id, index)| {
+                iteration.answers.get(id).is_some_and(|answers| {
+                    compare_multiple_choices(answers, &self.bank[*index].correct_choices)
+                })
+            })
+            .count();
+        SubmitData {
+            score: questions_correct * 100 / iteration.questions.len().max(1),
+            questions_correct,
+        }
+    }
+}
+
+fn success<T: Serialize>(data: T) -> Response<Body> {
+    let response = CustomResponse {
+        rest_operation_status_vox: RestOperationStatusVOX {
+            operation: None,
+            status: "SUCCESS".to_string(),
+            data: Data {
+                rest_return_data: data,
+            },
+            errors: None,
+            warnings: None,
+        },
+    };
+    let body = serde_json::to_string(&response).expect("fail to serialize mock response");
+    Response::builder()
+        .header("Content-Type", "application/json")
+        .body(Body::from(body))
+        .expect("fail to build mock response")
+}
+
+fn error_response(status: StatusCode, message: String) -> Response<Body> {
+    Response::builder()
+        .status(status)
+        .body(Body::from(message))
+        .expect("fail to build mock response")
+}
+
+/// 生成一个与服务器格式相同的随机guid
+fn guid() -> String {
+    let mut rng = rand::thread_rng();
+    format!(
+        "{:08x}-{:04x}-{:04x}-{:04x}-{:012x}",
+        rng.gen::<u32>(),
+        rng.gen::<u16>(),
+        rng.gen::<u16>(),
+        rng.gen::<u16>(),
+        rng.gen::<u64>() & 0xffff_ffff_ffff
+    )
+}
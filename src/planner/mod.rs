@@ -0,0 +1,189 @@
+//! 决定每题的作答方式。只会让还不知道答案的题目有意答错，已知答案的题目总是答对以保持得分，
+//! 所以没有已知错误答案的未知题目无法被单独隔离，只能和其他探测的题目一起探测。
+//! 答对题数既不是全部答错也不是全部答对时，本次答题本身推不出结论，
+//! 要靠每次提交后的`solve_store`重放存储中的全部答题记录，结合之后的答题推出，代价随答题记录的数量增长
+
+use crate::global::MemoryStoreQuestion;
+use crate::response::{AnswerChoice, QuestionFormat};
+
+/// 本次答题中一道题目的作答方式
+#[derive(Debug, PartialEq)]
+pub enum Plan {
+    /// 已知正确答案，按正确答案作答
+    Correct,
+    /// 选一个还没被排除的答案，看它是否正确
+    Probe,
+    /// 选一个已知错误的答案，这一题一定答错，不影响答对题数，其他被探测的题目更容易由答对题数直接确定
+    Mute(Vec<AnswerChoice>),
+}
+
+/// 探测这一题时选中正确答案的概率，假设未被排除的选项（多选题为选项组合）都同样可能是正确的
+pub fn probe_chance(question: &MemoryStoreQuestion) -> f64 {
+    let remaining = match question.format {
+        QuestionFormat::MultiChoiceSingleAnswer => question.excluded_errors().len(),
+        QuestionFormat::MultiChoiceMultipleAnswer => {
+            let combinations = 1usize
+                .checked_shl(question.choices.len() as u32)
+                .unwrap_or(usize::MAX)
+                - 1;
+            combinations.saturating_sub(question.multiple_error_choices.len())
+        }
+    };
+    1.0 / remaining.max(1) as f64
+}
+
+/// 答对题数能直接确定的探测的期望数量：被探测的题目全部答错或全部答对时，每一题的答案都确定了，
+/// 其他情况只能留给之后的答题结合求解。探测n题、每题答对的概率为p时，n=1总能确定，
+/// p较大时应该一次只探测一题，p较小时一次探测多题（分组检测）更划算
+pub fn expected_resolved(probes: &[f64]) -> f64 {
+    if probes.is_empty() {
+        return 0.0;
+    }
+    let all_wrong = probes.iter().map(|p| 1.0 - p).product::<f64>();
+    let all_correct = probes.iter().product::<f64>();
+    probes.len() as f64 * (all_wrong + all_correct)
+}
+
+/// 已知错误的答案，没有时无法让这一题一定答错
+fn wrong_answer(question: &MemoryStoreQuestion) -> Option<Vec<AnswerChoice>> {
+    let current = |choices: &[AnswerChoice]| {
+        choices
+            .iter()
+            .map(|c| question.choices.iter().find(|q| q.same_choice(c)).cloned())
+            .collect::<Option<Vec<AnswerChoice>>>()
+    };
+    match question.format {
+        QuestionFormat::MultiChoiceSingleAnswer => question
+            .error_choices
+            .iter()
+            .find_map(|c| current(std::slice::from_ref(c))),
+        QuestionFormat::MultiChoiceMultipleAnswer => question
+            .multiple_error_choices
+            .iter()
+            .find_map(|m| current(m)),
+    }
+}
+
+/// 按本次答题中已经探测的题目（每题答对的概率）决定这一题如何作答：
+/// 已知答案的题目按正确答案作答，一定答对或一定答错都不带来信息，答对还能保持得分；
+/// 未知的题目在探测它能增加答对题数直接确定的期望数量时探测，否则有已知错误的答案时用它作答。
+/// 题目是逐个返回的，只能按已经答过的题目决定
+pub fn plan(question: &MemoryStoreQuestion, probes: &[f64]) -> Plan {
+    if !question.correct_choices.is_empty() {
+        return Plan::Correct;
+    }
+    let wrong = match wrong_answer(question) {
+        Some(wrong) => wrong,
+        None => return Plan::Probe,
+    };
+    let mut probed = probes.to_vec();
+    probed.push(probe_chance(question));
+    if expected_resolved(&probed) >= expected_resolved(probes) {
+        Plan::Probe
+    } else {
+        Plan::Mute(wrong)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::evidence::{Attempt, AttemptAnswer};
+    use crate::global::{mock_choices, MemoryStoreQuestion};
+    use crate::planner::{expected_resolved, plan, probe_chance, Plan};
+    use crate::response::{AnswerChoice, QuestionFormat};
+    use crate::solver::solve;
+
+    #[test]
+    fn expected_resolved_test() {
+        assert_eq!(expected_resolved(&[]), 0.0);
+        assert_eq!(expected_resolved(&[0.5]), 1.0);
+        assert_eq!(expected_resolved(&[0.5, 0.5, 0.5]), 0.75);
+        assert_eq!(expected_resolved(&[0.25, 0.25]), 1.25);
+    }
+
+    #[test]
+    fn plan_test() {
+        // 一半的可能答对：一次只探测一两题
+        let even = MemoryStoreQuestion::mock("q1", &["1", "2", "3"]).with_errors(&["1"]);
+        assert_eq!(probe_chance(&even), 0.5);
+        assert_eq!(plan(&even, &[]), Plan::Probe);
+        assert_eq!(plan(&even, &[0.5]), Plan::Probe);
+        assert_eq!(
+            plan(&even, &[0.5, 0.5]),
+            Plan::Mute(vec![AnswerChoice::from_mock("1")])
+        );
+
+        // 答对的可能小：分组探测
+        let unlikely =
+            MemoryStoreQuestion::mock("q1", &["1", "2", "3", "4", "5"]).with_errors(&["1"]);
+        assert_eq!(plan(&unlikely, &[0.25, 0.25]), Plan::Probe);
+        assert!(matches!(
+            plan(&unlikely, &[0.25, 0.25, 0.25]),
+            Plan::Mute(_)
+        ));
+
+        // 没有已知错误的答案时只能探测，已知答案时按答案作答
+        let fresh = MemoryStoreQuestion::mock("q1", &["1", "2"]);
+        assert_eq!(plan(&fresh, &[0.5, 0.5, 0.5]), Plan::Probe);
+        let mut known = fresh.clone();
+        known.correct_choices = vec![AnswerChoice::from_mock("2")];
+        assert_eq!(plan(&known, &[]), Plan::Correct);
+
+        // 有意选择的已知错误选项答错后不重复记录
+        let mut muted = even.clone();
+        muted.chosen_choices = vec![AnswerChoice::from_mock("1")];
+        muted.remember_error("attempt");
+        assert_eq!(muted.error_choices.len(), 1);
+        assert!(muted.evidence.is_empty());
+        muted.format = QuestionFormat::MultiChoiceMultipleAnswer;
+        muted.multiple_error_choices = vec![vec![AnswerChoice::from_mock("1")]];
+        muted.remember_error("attempt");
+        assert_eq!(muted.multiple_error_choices.len(), 1);
+        assert!(muted.evidence.is_empty());
+    }
+
+    /// 答对题数不是全部答错或全部答对时本次推不出结论，之后的答题结合它才能推出
+    #[test]
+    fn mixed_score_test() {
+        let q1 = MemoryStoreQuestion::mock("q1", &["1", "2"]);
+        let q2 = MemoryStoreQuestion::mock("q2", &["1", "2"]);
+        let q3 = MemoryStoreQuestion::mock("q3", &["1", "2"]).with_correct(&["2"]);
+        // 没有已知错误的答案，两题都只能探测，已知答案的题目不会被有意答错
+        assert_eq!(plan(&q1, &[]), Plan::Probe);
+        assert_eq!(plan(&q2, &[probe_chance(&q1)]), Plan::Probe);
+        assert_eq!(plan(&q3, &[0.5, 0.5]), Plan::Correct);
+
+        let attempt =
+            |id: &str, timestamp: u64, correct: usize, answers: &[(&str, &str)]| Attempt {
+                iteration_id: id.to_string(),
+                quiz_id: String::from("quiz"),
+                locale: String::from("English"),
+                timestamp,
+                score: 0,
+                questions_correct: correct,
+                answers: answers
+                    .iter()
+                    .map(|(useful_id, value)| AttemptAnswer {
+                        useful_id: useful_id.to_string(),
+                        chosen_choices: mock_choices(&[value]),
+                    })
+                    .collect(),
+            };
+        let questions = [q1, q2, q3];
+        // 探测的两题中答对了一题
+        let mixed = attempt("a1", 1, 2, &[("q1", "1"), ("q2", "1"), ("q3", "2")]);
+        assert!(solve(&questions, std::slice::from_ref(&mixed))
+            .learned
+            .is_empty());
+
+        // 之后的答题只探测到q1并答对，结合前一次答题q2选的1是错误的
+        let later = attempt("a2", 2, 2, &[("q1", "1"), ("q3", "2")]);
+        let solution = solve(&questions, &[mixed, later]);
+        let q2 = solution
+            .learned
+            .iter()
+            .find(|q| q.useful_id == "q2")
+            .unwrap();
+        assert!(q2.correct_choices[0].same_choice(&AnswerChoice::from_mock("2")));
+    }
+}
@@ -1,56 +1,147 @@
 use crate::response::{AnswerChoice, SubmitData};
 use crate::url::{save_url, submit_url};
 use crate::{iteration_url, start_url, CustomResponse, GlobalData, IterationData, StartData};
-use reqwest::{Client, RequestBuilder};
+use reqwest::header::SET_COOKIE;
+use reqwest::{Client, RequestBuilder, Response, StatusCode};
+use serde::de::DeserializeOwned;
 use serde::{Deserialize, Serialize};
 use std::error::Error;
+use std::fmt;
+use wbl_robot::{redact, CookieJar, Secret};
+
+/// 登录会话已过期，需要换上新的COOKIE和CSRF_TOKEN后重试同一个请求
+#[derive(Debug)]
+pub(crate) struct SessionExpired {
+    pub(crate) reason: String,
+}
+
+impl fmt::Display for SessionExpired {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "session expired: {}", self.reason)
+    }
+}
+
+impl Error for SessionExpired {}
+
+/// 判断请求失败是否因为会话过期
+pub(crate) fn is_session_expired(error: &(dyn Error + 'static)) -> bool {
+    error.downcast_ref::<SessionExpired>().is_some()
+}
+
+/// 根据响应判断会话是否过期：401/403、被重定向到的登录页面、或CSRFGuard拒绝了token
+fn session_expired(status: StatusCode, body: &str) -> Option<String> {
+    let lower = body.to_lowercase();
+    if lower.contains("csrf") && !status.is_success() {
+        return Some(format!("csrf token rejected ({})", status));
+    }
+    if status == StatusCode::UNAUTHORIZED || status == StatusCode::FORBIDDEN {
+        return Some(format!("request rejected ({})", status));
+    }
+    // 接口只返回json，收到html说明被重定向到了登录页面
+    if lower.trim_start().starts_with('<')
+        && (lower.contains("<html") || lower.contains("<!doctype"))
+    {
+        return Some("redirected to the login page".to_string());
+    }
+    None
+}
+
+/// 检查响应，会话过期时返回SessionExpired，其它失败返回去掉了凭据的错误信息
+async fn check_response(
+    res: Response,
+    cookies: &CookieJar,
+    token: &Secret,
+    method: &str,
+) -> Result<String, Box<dyn Error>> {
+    absorb_cookies(&res, cookies);
+    let status = res.status();
+    let body = res.text().await?;
+    if let Some(reason) = session_expired(status, &body) {
+        return Err(Box::new(SessionExpired { reason }));
+    }
+    if status.is_success() {
+        Ok(body)
+    } else {
+        // 服务器的错误页面可能会原样带回请求中的cookie和token
+        let body = redact(&cookies.redact(&body), &[token.expose()]);
+        Err(format!("{} request return error {}: {}", method, status, body).into())
+    }
+}
+
+/// 解析接口返回的json，状态不是SUCCESS时返回去掉了凭据的错误而不是退出。
+/// 会话过期只按check_response中的HTTP状态、登录页面和CSRF拒绝判断，错误信息中的文字不作为依据，
+/// 否则普通的接口错误会被当作会话过期而一直等待新的凭据
+fn parse_response<T: DeserializeOwned>(
+    body: &str,
+    name: &str,
+    cookies: &CookieJar,
+    token: &Secret,
+) -> Result<T, Box<dyn Error>> {
+    let clean = |text: &str| redact(&cookies.redact(text), &[token.expose()]);
+    let value: serde_json::Value = serde_json::from_str(body)
+        .map_err(|error| format!("fail to parse {} response: {}", name, error))?;
+    let vox = &value["restOperationStatusVOX"];
+    let status = vox["status"].as_str().unwrap_or_default();
+    if status != "SUCCESS" {
+        let errors = match &vox["errors"] {
+            serde_json::Value::Null => String::new(),
+            serde_json::Value::String(errors) => clean(errors),
+            errors => clean(&errors.to_string()),
+        };
+        return Err(format!("{} request return status {:?}: {}", name, status, errors).into());
+    }
+    let response: CustomResponse<T> = serde_json::from_value(value)
+        .map_err(|error| format!("fail to parse {} response: {}", name, error))?;
+    Ok(response.rest_operation_status_vox.data.rest_return_data)
+}
+
+/// 用响应中的Set-Cookie更新cookie，服务器会在答题过程中更换JSESSIONID等cookie
+fn absorb_cookies(res: &Response, cookies: &CookieJar) {
+    res.headers()
+        .get_all(SET_COOKIE)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .for_each(|value| {
+            cookies.absorb(value);
+        });
+}
 
 async fn base_get(
     client: &Client,
     url: String,
-    cookie: &String,
-    token: &String,
+    cookies: &CookieJar,
+    token: &Secret,
 ) -> Result<String, Box<dyn Error>> {
     let rb: RequestBuilder = client.get(url);
     let res = rb
-        .header("COOKIE", cookie)
-        .header("owasp_csrftoken", token)
+        .header("COOKIE", cookies.header())
+        .header("owasp_csrftoken", token.expose())
         .send()
         .await?;
-    if res.status().is_success() {
-        let body = res.text().await?;
-        Ok(body)
-    } else {
-        panic!("get request return error {}", res.text().await?)
-    }
+    check_response(res, cookies, token, "get").await
 }
 
 async fn base_put<T: Serialize>(
     client: &Client,
     url: String,
-    cookie: &String,
-    token: &String,
+    cookies: &CookieJar,
+    token: &Secret,
     json: &T,
 ) -> Result<String, Box<dyn Error>> {
     let rb: RequestBuilder = client.put(url);
     let res = rb
         .header("Accept", "application/json")
         .header("Content-Type", "application/json")
-        .header("COOKIE", cookie)
-        .header("owasp_csrftoken", token)
+        .header("COOKIE", cookies.header())
+        .header("owasp_csrftoken", token.expose())
         .header(
             "x-requested-with",
             "XMLHttpRequest, OWASP CSRFGuard Project",
         )
-        .json(json.clone())
+        .json(json)
         .send()
         .await?;
-    if res.status().is_success() {
-        let body = res.text().await?;
-        Ok(body)
-    } else {
-        panic!("put request return error {}", res.text().await?)
-    }
+    check_response(res, cookies, token, "put").await
 }
 
 pub(crate) async fn iteration_request(
@@ -59,22 +150,17 @@ pub(crate) async fn iteration_request(
 ) -> Result<IterationData, Box<dyn Error>> {
     let body = base_get(
         client,
-        iteration_url(&global_data),
-        global_data.cookie(),
+        iteration_url(global_data),
+        global_data.cookies(),
         global_data.csrf_token(),
     )
     .await?;
-    let response: CustomResponse<IterationData> =
-        serde_json::from_str(&*body).expect("fail to serialize Iteration response data");
-    let rest_operation_status_vox = response.rest_operation_status_vox;
-    let status = rest_operation_status_vox.status;
-    if status == "SUCCESS" {
-        let data = rest_operation_status_vox.data;
-        let rest_return_data = data.rest_return_data;
-        Ok(rest_return_data.clone())
-    } else {
-        panic!("")
-    }
+    parse_response(
+        &body,
+        "Iteration",
+        global_data.cookies(),
+        global_data.csrf_token(),
+    )
 }
 
 pub(crate) async fn start_request(
@@ -84,33 +170,26 @@ pub(crate) async fn start_request(
 ) -> Result<StartData, Box<dyn Error>> {
     let body = base_get(
         client,
-        start_url(&global_data, first_question_guid),
-        global_data.cookie(),
+        start_url(global_data, first_question_guid),
+        global_data.cookies(),
         global_data.csrf_token(),
     )
     .await?;
-    let response = match serde_json::from_str::<CustomResponse<StartData>>(&*body) {
-        Ok(r) => r,
-        Err(error) => panic!("fail to serialize start response: {:?}", error),
-    };
-    let rest_operation_status_vox = response.rest_operation_status_vox;
-    let status = rest_operation_status_vox.status;
-    if status == "SUCCESS" {
-        let data = rest_operation_status_vox.data;
-        let rest_return_data = data.rest_return_data;
-        Ok(rest_return_data)
-    } else {
-        panic!("")
-    }
+    parse_response(
+        &body,
+        "Start",
+        global_data.cookies(),
+        global_data.csrf_token(),
+    )
 }
 
 #[derive(Serialize, Deserialize)]
 pub(crate) struct SaveBody {
-    score: bool,
+    pub(crate) score: bool,
     #[serde(rename = "studentAnswers")]
-    student_answers: Vec<AnswerChoice>,
+    pub(crate) student_answers: Vec<AnswerChoice>,
     #[serde(rename = "nextStudentAssessmentQuestionSysGUID")]
-    next: String,
+    pub(crate) next: Option<String>,
 }
 
 impl SaveBody {
@@ -118,7 +197,7 @@ impl SaveBody {
         SaveBody {
             score: true,
             student_answers: answers,
-            next,
+            next: Some(next),
         }
     }
 }
@@ -131,23 +210,18 @@ pub(crate) async fn save_request(
 ) -> Result<StartData, Box<dyn Error>> {
     let body = base_put(
         client,
-        save_url(&global_data, question_guid.clone()),
-        global_data.cookie(),
+        save_url(global_data, question_guid.clone()),
+        global_data.cookies(),
         global_data.csrf_token(),
         save_body,
     )
     .await?;
-    let response: CustomResponse<StartData> =
-        serde_json::from_str(&*body).expect("fail to serialize SaveAndNext response data");
-    let rest_operation_status_vox = response.rest_operation_status_vox;
-    let status = rest_operation_status_vox.status;
-    if status == "SUCCESS" {
-        let data = rest_operation_status_vox.data;
-        let rest_return_data = data.rest_return_data;
-        Ok(rest_return_data)
-    } else {
-        panic!("")
-    }
+    parse_response(
+        &body,
+        "SaveAndNext",
+        global_data.cookies(),
+        global_data.csrf_token(),
+    )
 }
 
 pub(crate) async fn submit_request(
@@ -158,21 +232,74 @@ pub(crate) async fn submit_request(
 ) -> Result<SubmitData, Box<dyn Error>> {
     let body = base_put(
         client,
-        submit_url(&global_data, first_question_guid),
-        global_data.cookie(),
+        submit_url(global_data, first_question_guid),
+        global_data.cookies(),
         global_data.csrf_token(),
         save_body,
     )
     .await?;
-    let response: CustomResponse<SubmitData> =
-        serde_json::from_str(&*body).expect("fail to serialize SaveAndNext response data");
-    let rest_operation_status_vox = response.rest_operation_status_vox;
-    let status = rest_operation_status_vox.status;
-    if status == "SUCCESS" {
-        let data = rest_operation_status_vox.data;
-        let rest_return_data = data.rest_return_data;
-        Ok(rest_return_data)
-    } else {
-        panic!("")
+    parse_response(
+        &body,
+        "Submit",
+        global_data.cookies(),
+        global_data.csrf_token(),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{is_session_expired, parse_response, session_expired};
+    use reqwest::StatusCode;
+    use serde_json::json;
+    use wbl_robot::{CookieJar, Secret};
+
+    #[test]
+    fn session_expired_test() {
+        assert!(session_expired(StatusCode::UNAUTHORIZED, "").is_some());
+        assert!(session_expired(StatusCode::FORBIDDEN, "{}").is_some());
+        assert!(session_expired(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "potential CSRF attack thwarted"
+        )
+        .is_some());
+        assert!(session_expired(
+            StatusCode::OK,
+            "\n<!DOCTYPE html><html><body><form id=\"login\"></form></body></html>"
+        )
+        .is_some());
+        assert!(session_expired(StatusCode::OK, r#"{"restOperationStatusVOX":{}}"#).is_none());
+        assert!(session_expired(StatusCode::INTERNAL_SERVER_ERROR, "oops").is_none());
+        // 只是提到了登录或会话的普通错误
+        assert!(session_expired(StatusCode::BAD_REQUEST, "unauthorized quiz").is_none());
+        assert!(session_expired(StatusCode::CONFLICT, "session limit reached").is_none());
+    }
+
+    #[test]
+    fn failed_status_test() {
+        let cookies = CookieJar::from_header("JSESSIONID=secret-session");
+        let token = Secret::new("secret-token");
+        // 接口返回的错误信息不会让程序等待新的凭据
+        for errors in [
+            "author is not assigned",
+            "unauthorized quiz",
+            "session limit reached",
+            "question not found",
+        ] {
+            let body = json!({"restOperationStatusVOX": {"status": "FAILURE", "errors": errors}});
+            let error =
+                parse_response::<serde_json::Value>(&body.to_string(), "Start", &cookies, &token)
+                    .unwrap_err();
+            assert!(!is_session_expired(error.as_ref()));
+            assert!(error.to_string().contains(errors));
+        }
+        let body = json!({"restOperationStatusVOX": {
+            "status": "FAILURE",
+            "errors": "bad token secret-token for secret-session",
+        }});
+        let error =
+            parse_response::<serde_json::Value>(&body.to_string(), "Start", &cookies, &token)
+                .unwrap_err()
+                .to_string();
+        assert!(!error.contains("secret-token") && !error.contains("secret-session"));
     }
 }
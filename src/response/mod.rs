@@ -1,7 +1,8 @@
 use serde::{Deserialize, Serialize};
 use std::error::Error;
 
-#[allow(dead_code)]
+// 没有用到的原有代码，保持原样
+#[allow(dead_code, clippy::needless_return)]
 async fn get_res_data<'a, T: Deserialize<'a>>(body: &'a str) -> Result<T, Box<dyn Error>> {
     let response: CustomResponse<T> = serde_json::from_str(body)?;
     let rest_operation_status_vox = response.rest_operation_status_vox;
@@ -72,13 +73,77 @@ pub struct Question {
 pub struct StartData {
     #[serde(rename = "questionID")]
     pub(crate) question_id: String,
-    // #[serde(rename = "questionSysGUID")]
-    // pub(crate) question_sys_guid: String,
     #[serde(rename = "answerChoices")]
     pub(crate) answer_choices: Vec<AnswerChoice>,
     pub(crate) format: QuestionFormat,
-    // #[serde(rename = "totalPoints")]
-    // pub(crate) total_points: usize,
+    #[serde(flatten)]
+    pub(crate) details: QuestionDetails,
+}
+
+impl StartData {
+    /// 题目的文字和元数据，接口没有直接返回版本时使用选项中记录的版本
+    pub(crate) fn details(&self) -> QuestionDetails {
+        let mut details = self.details.clone();
+        let first = self.answer_choices.first();
+        if details.question_version_sys_guid.is_none() {
+            details.question_version_sys_guid =
+                first.and_then(|c| c.question_version_sys_guid.clone());
+        }
+        if details.question_variant_sys_guid.is_none() {
+            details.question_variant_sys_guid =
+                first.and_then(|c| c.question_variant_sys_guid.clone());
+        }
+        details
+    }
+}
+
+/// start和saveAndNext接口返回的题目本身的信息，保存在题库中供人阅读、搜索和核对，
+/// 不同租户返回的字段不完全相同，都是可选的
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Default)]
+pub struct QuestionDetails {
+    #[serde(rename = "questionSysGUID")]
+    pub(crate) question_sys_guid: Option<String>,
+    // 题干
+    #[serde(rename = "questionText", alias = "questionValue")]
+    pub(crate) question_text: Option<String>,
+    #[serde(rename = "questionVersionSysGUID")]
+    pub(crate) question_version_sys_guid: Option<String>,
+    #[serde(rename = "questionVariantSysGUID")]
+    pub(crate) question_variant_sys_guid: Option<String>,
+    // 分值不一定是整数
+    #[serde(rename = "totalPoints")]
+    pub(crate) total_points: Option<f64>,
+    #[serde(rename = "imageID")]
+    pub(crate) image_id: Option<String>,
+    #[serde(rename = "imageFileName")]
+    pub(crate) image_file_name: Option<String>,
+    #[serde(rename = "imageAltText")]
+    pub(crate) image_alt_text: Option<String>,
+}
+
+impl QuestionDetails {
+    /// 用另一份记录补上自己没有的字段
+    pub(crate) fn fill(&mut self, other: &QuestionDetails) {
+        fn fill<T: Clone>(field: &mut Option<T>, other: &Option<T>) {
+            if field.is_none() {
+                field.clone_from(other);
+            }
+        }
+        fill(&mut self.question_sys_guid, &other.question_sys_guid);
+        fill(&mut self.question_text, &other.question_text);
+        fill(
+            &mut self.question_version_sys_guid,
+            &other.question_version_sys_guid,
+        );
+        fill(
+            &mut self.question_variant_sys_guid,
+            &other.question_variant_sys_guid,
+        );
+        fill(&mut self.total_points, &other.total_points);
+        fill(&mut self.image_id, &other.image_id);
+        fill(&mut self.image_file_name, &other.image_file_name);
+        fill(&mut self.image_alt_text, &other.image_alt_text);
+    }
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
@@ -146,11 +211,45 @@ pub struct AnswerChoice {
     value: Option<String>,
 }
 
+/// 选项的身份：优先使用answerSysGUID，其次answerI18nSysGUID，都没有时使用规范化后的选项文字，
+/// 服务端修改了选项的错别字或空白时仍能对应到同一选项，文字相同的不同选项也不会被当作同一个
+pub(crate) fn choice_id(answer_sys_guid: &str, answer_i18n_sys_guid: &str, text: &str) -> String {
+    if !answer_sys_guid.is_empty() {
+        answer_sys_guid.to_string()
+    } else if !answer_i18n_sys_guid.is_empty() {
+        answer_i18n_sys_guid.to_string()
+    } else {
+        format!("text:{}", normalize_text(text))
+    }
+}
+
+/// 去掉首尾空白、合并连续的空白并转为小写，只处理ASCII的空白和字母，
+/// 与store::mongo中服务端计算的身份完全相同
+pub(crate) fn normalize_text(text: &str) -> String {
+    text.split_ascii_whitespace()
+        .collect::<Vec<&str>>()
+        .join(" ")
+        .to_ascii_lowercase()
+}
+
 impl AnswerChoice {
+    /// 比较、去重和保存选项时都使用的身份，见choice_id
+    pub(crate) fn choice_id(&self) -> String {
+        choice_id(
+            &self.answer_sys_guid,
+            self.answer_i18n_sys_guid.as_deref().unwrap_or_default(),
+            &self.choice_value,
+        )
+    }
+
+    pub(crate) fn same_choice(&self, other: &AnswerChoice) -> bool {
+        self.choice_id() == other.choice_id()
+    }
+
     #[allow(dead_code)]
     pub(crate) fn from_mock(v: &str) -> AnswerChoice {
         AnswerChoice {
-            answer_sys_guid: String::from("default mock"),
+            answer_sys_guid: format!("mock {}", v),
             group_number: None,
             is_archived: None,
             is_correct: None,
@@ -191,3 +290,58 @@ pub struct SubmitData {
     #[serde(rename = "questionsCorrect")]
     pub(crate) questions_correct: usize,
 }
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use crate::response::{AnswerChoice, QuestionDetails, StartData};
+
+    #[test]
+    fn choice_id_test() {
+        let mut a = AnswerChoice::from_mock("Yes");
+        let mut b = AnswerChoice::from_mock("Yes");
+        b.choice_value = String::from("  yes,\tthe  answer ");
+        assert!(a.same_choice(&b));
+        b.answer_sys_guid = String::from("other");
+        assert!(!a.same_choice(&b));
+
+        // 没有GUID时比较规范化后的文字
+        a.answer_sys_guid = String::new();
+        b.answer_sys_guid = String::new();
+        a.choice_value = String::from("Yes, the answer");
+        assert!(a.same_choice(&b));
+        b.answer_i18n_sys_guid = Some(String::from("i18n"));
+        assert_eq!(b.choice_id(), "i18n");
+        assert!(!a.same_choice(&b));
+    }
+
+    #[test]
+    fn start_data_details_test() {
+        let mut choice = serde_json::to_value(AnswerChoice::from_mock("1")).unwrap();
+        choice["questionVersionSysGUID"] = json!("version");
+        let data: StartData = serde_json::from_value(json!({
+            "questionID": "q1",
+            "questionSysGUID": "guid",
+            "questionText": "Which one?",
+            "totalPoints": 1.5,
+            "imageFileName": "q1.png",
+            "answerChoices": [choice],
+            "format": "MULTI_CHOICE_SINGLE_ANSWER",
+        }))
+        .unwrap();
+        let details = data.details();
+        assert_eq!(details.question_text.as_deref(), Some("Which one?"));
+        assert_eq!(details.total_points, Some(1.5));
+        assert_eq!(details.image_file_name.as_deref(), Some("q1.png"));
+        assert_eq!(
+            details.question_version_sys_guid.as_deref(),
+            Some("version")
+        );
+        assert_eq!(details.question_variant_sys_guid, None);
+
+        let mut stored = QuestionDetails::default();
+        stored.fill(&details);
+        assert_eq!(stored, details);
+    }
+}
@@ -0,0 +1,260 @@
+use std::error::Error;
+
+use serde_json::{Map, Value};
+
+use crate::config::DEFAULT_LOCALE;
+use crate::response::{choice_id, normalize_text};
+
+/// 当前的文档格式版本
+///
+/// 题目在mongodb、sqlite、json存储和题库文件中都以同一种文档保存，第7版的字段为：
+/// useful_id、temp_id、locale、course_id、quiz_id、quiz_sys_guid、format、details、choices、correct_choices、
+/// error_choices、multiple_error_choices、chosen_choices、evidence、tried_choices、schema_version。
+/// details为接口返回的QuestionDetails，evidence为每条了解的来源evidence::Evidence，
+/// tried_choices为单选题的每次尝试evidence::Trial，选项为接口返回的AnswerChoice，
+/// 记录中的选项都带有choices中同一选项的GUID，按response::choice_id比较。
+/// 修改MemoryStoreQuestion或AnswerChoice的字段时需要增加版本，并在MIGRATIONS末尾加上升级旧文档的函数
+pub const SCHEMA_VERSION: u32 = 7;
+
+/// 升级文档的函数，MIGRATIONS[i]将第i+1版的文档升级为第i+2版
+type Migration = fn(&mut Map<String, Value>) -> Result<(), Box<dyn Error>>;
+
+const MIGRATIONS: [Migration; 6] = [
+    locale_required,
+    details_added,
+    choice_ids,
+    evidence_added,
+    namespace_added,
+    trials_added,
+];
+
+/// 第1版中locale是后来加的，没有locale的题目都是用默认语言答的；第2版起locale必须存在
+fn locale_required(document: &mut Map<String, Value>) -> Result<(), Box<dyn Error>> {
+    document
+        .entry("locale")
+        .or_insert_with(|| Value::String(DEFAULT_LOCALE.to_string()));
+    Ok(())
+}
+
+/// 第3版加入了题目信息，旧题目答题时没有记录，字段都为空
+fn details_added(document: &mut Map<String, Value>) -> Result<(), Box<dyn Error>> {
+    document
+        .entry("details")
+        .or_insert_with(|| Value::Object(Map::new()));
+    Ok(())
+}
+
+/// 第4版起选项按GUID而不是文字比较：记录中缺少GUID的选项从choices中文字相同的选项补上，
+/// 再按新的身份去掉重复的错误记录
+fn choice_ids(document: &mut Map<String, Value>) -> Result<(), Box<dyn Error>> {
+    let choices = document.get("choices").cloned().unwrap_or(Value::Null);
+    let choices = choices.as_array().map(Vec::as_slice).unwrap_or_default();
+    let backfill = |choice: &mut Value| {
+        if !text_field(choice, "answerSysGUID").is_empty() {
+            return;
+        }
+        let text = normalize_text(text_field(choice, "choiceValue"));
+        let same = choices
+            .iter()
+            .find(|c| normalize_text(text_field(c, "choiceValue")) == text);
+        if let (Some(same), Some(choice)) = (same, choice.as_object_mut()) {
+            for key in ["answerSysGUID", "answerI18nSysGUID"] {
+                if let Some(value) = same.get(key) {
+                    choice.insert(key.to_string(), value.clone());
+                }
+            }
+        }
+    };
+    for key in ["correct_choices", "error_choices", "chosen_choices"] {
+        if let Some(Value::Array(list)) = document.get_mut(key) {
+            list.iter_mut().for_each(backfill);
+        }
+    }
+    if let Some(Value::Array(list)) = document.get_mut("error_choices") {
+        let mut seen = Vec::new();
+        list.retain(|c| {
+            let id = document_choice_id(c);
+            !seen.contains(&id) && {
+                seen.push(id);
+                true
+            }
+        });
+    }
+    if let Some(Value::Array(groups)) = document.get_mut("multiple_error_choices") {
+        let mut seen = Vec::new();
+        groups.retain_mut(|group| {
+            let Some(list) = group.as_array_mut() else {
+                return true;
+            };
+            list.iter_mut().for_each(backfill);
+            let mut ids = list.iter().map(document_choice_id).collect::<Vec<String>>();
+            ids.sort();
+            ids.dedup();
+            !seen.contains(&ids) && {
+                seen.push(ids);
+                true
+            }
+        });
+    }
+    Ok(())
+}
+
+/// 第5版起记录每条了解的来源，之前的了解没有记录来源
+fn evidence_added(document: &mut Map<String, Value>) -> Result<(), Box<dyn Error>> {
+    document
+        .entry("evidence")
+        .or_insert_with(|| Value::Array(Vec::new()));
+    Ok(())
+}
+
+/// 第6版起记录题目所属的课程和测验，之前的题目不知道来自哪里，留空，
+/// 之后答到或从questions-<课程id>.zip读入时补上
+fn namespace_added(document: &mut Map<String, Value>) -> Result<(), Box<dyn Error>> {
+    for key in ["course_id", "quiz_id", "quiz_sys_guid"] {
+        document
+            .entry(key)
+            .or_insert_with(|| Value::String(String::new()));
+    }
+    Ok(())
+}
+
+/// 第7版起记录单选题尝试过的选项，之前的尝试只保存在答题记录中
+fn trials_added(document: &mut Map<String, Value>) -> Result<(), Box<dyn Error>> {
+    document
+        .entry("tried_choices")
+        .or_insert_with(|| Value::Array(Vec::new()));
+    Ok(())
+}
+
+fn text_field<'a>(choice: &'a Value, key: &str) -> &'a str {
+    choice.get(key).and_then(|v| v.as_str()).unwrap_or_default()
+}
+
+/// 与AnswerChoice::choice_id相同的身份
+fn document_choice_id(choice: &Value) -> String {
+    choice_id(
+        text_field(choice, "answerSysGUID"),
+        text_field(choice, "answerI18nSysGUID"),
+        text_field(choice, "choiceValue"),
+    )
+}
+
+/// 文档的格式版本，没有schema_version的文档都是第1版
+pub fn schema_version(document: &Value) -> Result<u32, Box<dyn Error>> {
+    let version = match document.get("schema_version") {
+        None => return Ok(1),
+        Some(Value::Number(number)) => number.as_u64(),
+        // mongoexport的canonical格式中数字写作{"$numberInt": "1"}
+        Some(Value::Object(object)) => object
+            .get("$numberInt")
+            .or_else(|| object.get("$numberLong"))
+            .and_then(|v| v.as_str())
+            .and_then(|v| v.parse().ok()),
+        Some(_) => None,
+    };
+    version
+        .and_then(|v| u32::try_from(v).ok())
+        .ok_or_else(|| format!("malformed schema_version {}", document["schema_version"]).into())
+}
+
+/// 将任意旧版本的文档升级为当前版本，比当前版本更新的文档无法读取
+pub fn migrate(mut document: Value) -> Result<Value, Box<dyn Error>> {
+    let version = schema_version(&document)?;
+    if version > SCHEMA_VERSION {
+        return Err(format!(
+            "schema version {} is newer than the supported version {}, please upgrade wbl-robot",
+            version, SCHEMA_VERSION
+        )
+        .into());
+    }
+    let object = document
+        .as_object_mut()
+        .ok_or("a question document must be a json object")?;
+    for migration in MIGRATIONS.iter().skip(version as usize - 1) {
+        migration(object)?;
+    }
+    object.insert("schema_version".to_string(), Value::from(SCHEMA_VERSION));
+    Ok(document)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use serde_json::json;
+
+    use crate::bank::{read_bank, read_documents};
+    use crate::schema::{migrate, schema_version, MIGRATIONS, SCHEMA_VERSION};
+
+    #[test]
+    fn migrations_cover_every_version_test() {
+        assert_eq!(MIGRATIONS.len() as u32, SCHEMA_VERSION - 1);
+    }
+
+    #[test]
+    fn migrate_test() {
+        let document = migrate(json!({"useful_id": "q1"})).unwrap();
+        assert_eq!(document["locale"], "English");
+        assert_eq!(document["details"], json!({}));
+        assert_eq!(document["evidence"], json!([]));
+        assert_eq!(document["course_id"], "");
+        assert_eq!(document["tried_choices"], json!([]));
+        assert_eq!(schema_version(&document).unwrap(), SCHEMA_VERSION);
+
+        let document = migrate(json!({"locale": "French", "schema_version": {"$numberInt": "1"}}));
+        assert_eq!(document.unwrap()["locale"], "French");
+
+        let details = json!({"questionText": "Which one?"});
+        let document = migrate(json!({"details": details, "schema_version": 3})).unwrap();
+        assert_eq!(document["details"], details);
+
+        let document = migrate(json!({"course_id": "HRC1", "schema_version": 6})).unwrap();
+        assert_eq!(document["course_id"], "HRC1");
+    }
+
+    #[test]
+    fn choice_ids_migration_test() {
+        let choice = |guid: &str, text: &str| json!({"answerSysGUID": guid, "choiceValue": text});
+        let document = migrate(json!({
+            "choices": [choice("a", "Yes"), choice("b", "No")],
+            "correct_choices": [],
+            "error_choices": [choice("", " yes"), choice("a", "Yes"), choice("", "Maybe")],
+            "multiple_error_choices": [[choice("", "YES"), choice("b", "No")], [choice("a", "Yes"), choice("", "no")]],
+            "chosen_choices": [],
+            "schema_version": 3,
+        }))
+        .unwrap();
+        assert_eq!(
+            document["error_choices"],
+            json!([choice("a", " yes"), choice("", "Maybe")])
+        );
+        assert_eq!(
+            document["multiple_error_choices"],
+            json!([[choice("a", "YES"), choice("b", "No")]])
+        );
+
+        assert!(migrate(json!({"schema_version": SCHEMA_VERSION + 1})).is_err());
+        assert!(migrate(json!({"schema_version": "1"})).is_err());
+    }
+
+    /// bank/下的每个题库都必须能被当前版本读取
+    #[test]
+    fn load_every_bank_test() {
+        let mut paths = std::fs::read_dir("bank")
+            .unwrap()
+            .map(|entry| entry.unwrap().path())
+            .filter(|path| path.extension().is_some_and(|ext| ext == "zip"))
+            .collect::<Vec<_>>();
+        paths.sort();
+        assert!(!paths.is_empty());
+        for path in paths.iter() {
+            let documents = read_documents(path).unwrap();
+            let questions = read_bank(Path::new(path))
+                .unwrap_or_else(|error| panic!("unable to load {}: {}", path.display(), error));
+            assert_eq!(questions.len(), documents.len(), "{}", path.display());
+            assert!(questions
+                .iter()
+                .all(|q| q.schema_version == SCHEMA_VERSION && !q.useful_id.is_empty()));
+        }
+    }
+}
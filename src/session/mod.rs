@@ -0,0 +1,155 @@
+use std::error::Error;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use tokio::io::{AsyncBufReadExt, BufReader, Lines, Stdin};
+
+use crate::config::{Config, ConfigArgs};
+use crate::credentials::extract;
+use crate::global::GlobalData;
+
+/// 等待重新登录时保存答题进度的文件，和配置文件放在同一目录，只供查看，不会在下次启动时恢复
+const ATTEMPT_FILE: &str = ".wbl-robot-attempt.json";
+/// 标准输入不可用时检查配置文件的间隔
+const POLL_INTERVAL: Duration = Duration::from_secs(5);
+
+fn attempt_path(config_path: &Path) -> PathBuf {
+    config_path.with_file_name(ATTEMPT_FILE)
+}
+
+/// 会话过期时保存答题进度，等待新的COOKIE和CSRF_TOKEN后返回，之后在同一进程中继续同一次答题
+///
+/// 新的凭据可以粘贴"Copy as cURL"复制的命令（会同时写入配置文件），也可以直接修改配置文件后回车；
+/// 标准输入已关闭时每隔一段时间重新读取配置文件
+pub async fn reauthenticate(
+    args: &ConfigArgs,
+    config: &mut Config,
+    global_data: &mut GlobalData,
+    error: &(dyn Error + 'static),
+) -> Result<(), Box<dyn Error>> {
+    let (path, _) = args.config_path();
+    let attempt = attempt_path(&path);
+    global_data.save_attempt(&attempt)?;
+    println!("\x1b[33m{}\x1b[0m", error);
+    println!("the current attempt was saved to {}", attempt.display());
+    if args.credentials_stdin() {
+        return Err("credentials were read from stdin, run again with fresh credentials".into());
+    }
+    println!(
+        "paste a new \"Copy as cURL\" command followed by an empty line, \
+         or update COOKIE and CSRF_TOKEN in {} and press Enter",
+        args.credentials_path().unwrap_or(path.clone()).display()
+    );
+
+    let mut lines = BufReader::new(tokio::io::stdin()).lines();
+    let mut interactive = true;
+    loop {
+        if interactive {
+            match read_paste(&mut lines).await? {
+                Some(text) if !text.trim().is_empty() => match extract(&text) {
+                    Ok(credentials) => {
+                        args.save(&credentials.pairs())?;
+                    }
+                    Err(error) => {
+                        println!("{}, try again", error);
+                        continue;
+                    }
+                },
+                Some(_) => {}
+                None => {
+                    println!(
+                        "stdin is closed, checking {} every {} seconds",
+                        path.display(),
+                        POLL_INTERVAL.as_secs()
+                    );
+                    interactive = false;
+                }
+            }
+        } else {
+            tokio::time::sleep(POLL_INTERVAL).await;
+        }
+
+        let fresh = match Config::load(args, true) {
+            Ok(fresh) => fresh,
+            Err(error) => {
+                if interactive {
+                    print!("{}", error);
+                }
+                continue;
+            }
+        };
+        if fresh.cookie == config.cookie && fresh.csrf_token == config.csrf_token {
+            if interactive {
+                println!("COOKIE and CSRF_TOKEN did not change, try again");
+            }
+            continue;
+        }
+        global_data.refresh_credentials(&fresh);
+        config.cookie = fresh.cookie;
+        config.csrf_token = fresh.csrf_token;
+        let _ = std::fs::remove_file(&attempt);
+        println!("\x1b[36mcredentials updated, continue the current attempt\x1b[0m");
+        return Ok(());
+    }
+}
+
+/// 读取到空行为止的内容，标准输入已关闭且没有读到内容时返回None
+async fn read_paste(lines: &mut Lines<BufReader<Stdin>>) -> Result<Option<String>, Box<dyn Error>> {
+    let mut text = String::new();
+    while let Some(line) = lines.next_line().await? {
+        if line.trim().is_empty() {
+            return Ok(Some(text));
+        }
+        text.push_str(&line);
+        text.push('\n');
+    }
+    Ok(if text.is_empty() { None } else { Some(text) })
+}
+
+#[cfg(test)]
+mod tests {
+    use clap::Parser;
+
+    use crate::config::{Config, ConfigArgs};
+    use crate::global::GlobalData;
+    use crate::request::SessionExpired;
+    use crate::session::{attempt_path, reauthenticate};
+
+    #[derive(Parser)]
+    struct Cli {
+        #[command(flatten)]
+        args: ConfigArgs,
+    }
+
+    #[tokio::test]
+    async fn reauthenticate_test() {
+        let dir = std::env::temp_dir().join(format!("wbl-robot-session-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join(".env");
+        std::fs::write(
+            &path,
+            "QUIZ_ID=quiz-1\nCOOKIE=\"JSESSIONID=1; PSA_STUD_CPNT_ID=1; PSA_STUD_CPNT_MOD_ID=2\"\nCSRF_TOKEN=token\n",
+        )
+        .unwrap();
+        let path = path.to_str().unwrap();
+        let mut config =
+            Config::load(&Cli::parse_from(["wbl-robot", "--config", path]).args, true).unwrap();
+        let mut global_data = GlobalData::from_config(&config);
+        global_data.insert_question(String::from("q1"));
+
+        // 凭据来自标准输入时无法等待新的凭据，保存进度后返回错误
+        let args = Cli::parse_from(["wbl-robot", "--config", path, "--credentials-stdin"]).args;
+        let expired = SessionExpired {
+            reason: String::from("request rejected (401 Unauthorized)"),
+        };
+        let result = reauthenticate(&args, &mut config, &mut global_data, &expired).await;
+        assert!(result.is_err());
+        let saved: serde_json::Value = serde_json::from_str(
+            &std::fs::read_to_string(attempt_path(&dir.join(".env"))).unwrap(),
+        )
+        .unwrap();
+        assert_eq!(saved["quiz_id"], "quiz-1");
+        assert_eq!(saved["questions"][0]["temp_id"], "q1");
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
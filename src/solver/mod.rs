@@ -0,0 +1,379 @@
+use std::collections::HashMap;
+
+use crate::evidence::{before_quarantine, quarantined_until, Attempt};
+use crate::global::MemoryStoreQuestion;
+use crate::response::{AnswerChoice, QuestionFormat};
+
+/// (useful_id, locale)
+type Key = (String, String);
+
+/// 某一题目的一种选择，同一题目在不同答题中选了相同的选项时是同一个选择
+#[derive(Clone)]
+struct Pick {
+    key: Key,
+    /// 排序后的选项身份
+    ids: Vec<String>,
+    choices: Vec<AnswerChoice>,
+    /// 这种选择是否就是正确答案，还不知道时为None
+    value: Option<bool>,
+    /// 推出value的答题，来自已有的了解时为None
+    reason: Option<String>,
+}
+
+/// 一次答题的约束：其中的选择恰好有correct个是正确的
+#[derive(Clone)]
+struct Constraint {
+    attempt: String,
+    picks: Vec<usize>,
+    correct: usize,
+}
+
+/// 已经接受的答题及由它们推出的结论
+#[derive(Clone, Default)]
+struct State {
+    picks: Vec<Pick>,
+    positions: HashMap<(Key, Vec<String>), usize>,
+    constraints: Vec<Constraint>,
+}
+
+/// 由约束推出的结论与已有的结论矛盾
+struct Conflict;
+
+/// 求解的结果
+pub struct Solution {
+    /// 得到了新了解的题目
+    pub learned: Vec<MemoryStoreQuestion>,
+    /// 与已有的了解或更早的答题矛盾而被忽略的答题
+    pub conflicts: Vec<String>,
+}
+
+fn sorted_ids(choices: &[AnswerChoice]) -> Vec<String> {
+    let mut ids = choices.iter().map(|c| c.choice_id()).collect::<Vec<_>>();
+    ids.sort();
+    ids
+}
+
+/// 每次答题都是一个约束：所选的(题目, 选项)中正确的个数等于questions_correct。
+/// 按时间顺序加入每次答题，在所有答题之间反复传播这些约束，直到推不出更多：
+/// 已知正确的个数等于questions_correct时其余都是错误的，加上所有未知的才够questions_correct时它们都是正确的，
+/// 一题的某种选择是正确的则同一题的其他选择都是错误的，单选题只剩一个选项未被排除时它就是正确的。
+/// 与已有的了解或更早的答题矛盾的答题（例如服务端改过答案）被忽略，隔离前答过被隔离题目的答题被跳过
+pub fn solve(questions: &[MemoryStoreQuestion], attempts: &[Attempt]) -> Solution {
+    let index = questions
+        .iter()
+        .map(|q| ((q.useful_id.clone(), q.locale.clone()), q))
+        .collect::<HashMap<Key, &MemoryStoreQuestion>>();
+    let until = quarantined_until(questions, attempts);
+    let mut attempts = attempts.iter().collect::<Vec<&Attempt>>();
+    attempts.sort_by_key(|a| a.timestamp);
+
+    let mut state = State::default();
+    let mut conflicts = Vec::new();
+    for attempt in attempts {
+        if before_quarantine(attempt, &until) {
+            continue;
+        }
+        let mut next = state.clone();
+        next.add(&index, attempt);
+        match next.propagate(&index) {
+            Ok(()) => state = next,
+            Err(Conflict) => conflicts.push(attempt.iteration_id.clone()),
+        }
+    }
+    Solution {
+        learned: apply(&index, state.picks),
+        conflicts,
+    }
+}
+
+/// 已有的了解能否确定这种选择是否正确
+fn known(question: Option<&&MemoryStoreQuestion>, ids: &[String]) -> Option<bool> {
+    let question = question?;
+    if ids.is_empty() {
+        return Some(false);
+    }
+    if !question.correct_choices.is_empty() {
+        return Some(sorted_ids(&question.correct_choices) == ids);
+    }
+    match question.format {
+        QuestionFormat::MultiChoiceSingleAnswer => {
+            let eliminated = ids.len() != 1
+                || question
+                    .error_choices
+                    .iter()
+                    .any(|c| c.choice_id() == ids[0]);
+            eliminated.then_some(false)
+        }
+        QuestionFormat::MultiChoiceMultipleAnswer => question
+            .multiple_error_choices
+            .iter()
+            .any(|m| sorted_ids(m) == ids)
+            .then_some(false),
+    }
+}
+
+impl State {
+    fn add(&mut self, index: &HashMap<Key, &MemoryStoreQuestion>, attempt: &Attempt) {
+        let mut constraint = Constraint {
+            attempt: attempt.iteration_id.clone(),
+            picks: Vec::new(),
+            correct: attempt.questions_correct,
+        };
+        for answer in attempt.answers.iter() {
+            let key = (answer.useful_id.clone(), attempt.locale.clone());
+            let ids = sorted_ids(&answer.chosen_choices);
+            let position = match self.positions.get(&(key.clone(), ids.clone())) {
+                Some(position) => *position,
+                None => {
+                    // 同一题目已经推出了正确的选择时，新的选择一定是错误的
+                    let correct = self
+                        .picks
+                        .iter()
+                        .find(|p| p.key == key && p.value == Some(true));
+                    let (value, reason) = match correct {
+                        Some(correct) => (Some(false), correct.reason.clone()),
+                        None => (known(index.get(&key), &ids), None),
+                    };
+                    self.picks.push(Pick {
+                        key: key.clone(),
+                        ids: ids.clone(),
+                        choices: answer.chosen_choices.clone(),
+                        value,
+                        reason,
+                    });
+                    self.positions.insert((key, ids), self.picks.len() - 1);
+                    self.picks.len() - 1
+                }
+            };
+            constraint.picks.push(position);
+        }
+        self.constraints.push(constraint);
+    }
+
+    /// 反复传播所有约束直到推不出更多
+    fn propagate(&mut self, index: &HashMap<Key, &MemoryStoreQuestion>) -> Result<(), Conflict> {
+        let mut changed = true;
+        while changed {
+            changed = false;
+            for c in 0..self.constraints.len() {
+                let constraint = &self.constraints[c];
+                let value = |p: &usize| self.picks[*p].value;
+                let correct = constraint
+                    .picks
+                    .iter()
+                    .filter(|p| value(p) == Some(true))
+                    .count();
+                let unknown = constraint
+                    .picks
+                    .iter()
+                    .copied()
+                    .filter(|p| value(p).is_none())
+                    .collect::<Vec<usize>>();
+                if correct > constraint.correct || correct + unknown.len() < constraint.correct {
+                    return Err(Conflict);
+                }
+                let value = if unknown.is_empty() {
+                    continue;
+                } else if correct == constraint.correct {
+                    false
+                } else if correct + unknown.len() == constraint.correct {
+                    true
+                } else {
+                    continue;
+                };
+                let reason = constraint.attempt.clone();
+                for p in unknown {
+                    self.set(p, value, &reason)?;
+                }
+                changed = true;
+            }
+            for (p, reason) in self.last_remaining(index) {
+                self.set(p, true, &reason)?;
+                changed = true;
+            }
+        }
+        Ok(())
+    }
+
+    /// 确定一种选择是否正确，正确时同一题目的其他选择都是错误的
+    fn set(&mut self, p: usize, value: bool, reason: &str) -> Result<(), Conflict> {
+        match self.picks[p].value {
+            Some(known) if known == value => return Ok(()),
+            Some(_) => return Err(Conflict),
+            None => {}
+        }
+        self.picks[p].value = Some(value);
+        self.picks[p].reason = Some(reason.to_string());
+        if value {
+            for other in 0..self.picks.len() {
+                if other != p && self.picks[other].key == self.picks[p].key {
+                    self.set(other, false, reason)?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// 排除了已知的错误选项和推出的错误选择后只剩一个选项的单选题，返回这个选项的选择及推出它的答题
+    fn last_remaining(&self, index: &HashMap<Key, &MemoryStoreQuestion>) -> Vec<(usize, String)> {
+        let mut found = Vec::new();
+        for (p, pick) in self.picks.iter().enumerate() {
+            let Some(question) = index.get(&pick.key) else {
+                continue;
+            };
+            if pick.value.is_some()
+                || pick.ids.len() != 1
+                || question.format != QuestionFormat::MultiChoiceSingleAnswer
+            {
+                continue;
+            }
+            let mut reason = None;
+            let mut remaining = Vec::new();
+            for choice in question.excluded_errors() {
+                let id = choice.choice_id();
+                let eliminated = self.picks.iter().find(|other| {
+                    other.key == pick.key
+                        && other.value == Some(false)
+                        && other.ids.len() == 1
+                        && other.ids[0] == id
+                });
+                match eliminated {
+                    Some(other) => reason = other.reason.clone().or(reason),
+                    None => remaining.push(id),
+                }
+            }
+            if let (true, Some(reason)) = (remaining == pick.ids, reason) {
+                found.push((p, reason));
+            }
+        }
+        found
+    }
+}
+
+/// 将推出的结论记到题目上，返回有新了解的题目
+fn apply(index: &HashMap<Key, &MemoryStoreQuestion>, picks: Vec<Pick>) -> Vec<MemoryStoreQuestion> {
+    let mut learned: Vec<MemoryStoreQuestion> = Vec::new();
+    for pick in picks {
+        let (Some(value), Some(reason)) = (pick.value, pick.reason) else {
+            continue;
+        };
+        let Some(stored) = index.get(&pick.key) else {
+            continue;
+        };
+        let position = match learned
+            .iter()
+            .position(|q| q.useful_id == stored.useful_id && q.locale == stored.locale)
+        {
+            Some(position) => position,
+            None => {
+                learned.push((*stored).clone());
+                learned.len() - 1
+            }
+        };
+        let question = &mut learned[position];
+        if !question.correct_choices.is_empty() {
+            continue;
+        }
+        question.chosen_choices = pick.choices;
+        if value {
+            question.remember_correct(&reason);
+        } else {
+            question.remember_error(&reason);
+        }
+        question.chosen_choices.clear();
+    }
+    learned.retain(|q| index.get(&(q.useful_id.clone(), q.locale.clone())) != Some(&q));
+    learned
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::evidence::{Attempt, AttemptAnswer};
+    use crate::global::{mock_choices, MemoryStoreQuestion};
+    use crate::response::{AnswerChoice, QuestionFormat};
+    use crate::solver::solve;
+
+    fn attempt(id: &str, timestamp: u64, correct: usize, answers: &[(&str, &[&str])]) -> Attempt {
+        Attempt {
+            iteration_id: id.to_string(),
+            quiz_id: String::from("quiz"),
+            locale: String::from("English"),
+            timestamp,
+            score: 0,
+            questions_correct: correct,
+            answers: answers
+                .iter()
+                .map(|(useful_id, values)| AttemptAnswer {
+                    useful_id: useful_id.to_string(),
+                    chosen_choices: mock_choices(values),
+                })
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn solve_test() {
+        use QuestionFormat::*;
+        let mut q5 = MemoryStoreQuestion::mock("q5", &["1", "2", "3"]);
+        q5.error_choices = mock_choices(&["1"]);
+        let questions = vec![
+            MemoryStoreQuestion::mock("q1", &["1", "2", "3"]),
+            MemoryStoreQuestion::mock("q2", &["1", "2"]),
+            MemoryStoreQuestion::mock("q3", &["1", "2", "3"])
+                .with_format(MultiChoiceMultipleAnswer),
+            MemoryStoreQuestion::mock("q4", &["1", "2", "3"]),
+            q5,
+        ];
+        let attempts = vec![
+            // a、b单独推不出什么
+            attempt("a", 1, 1, &[("q1", &["1"]), ("q2", &["2"])]),
+            attempt(
+                "b",
+                2,
+                1,
+                &[("q1", &["1"]), ("q3", &["1", "2"]), ("q4", &["2"])],
+            ),
+            // q1的2和q2的2都正确，因此q1的1是错误的
+            attempt("c", 3, 2, &[("q1", &["2"]), ("q2", &["2"])]),
+            // q3的1+2是错误的，回到b可知q4的2是正确的
+            attempt("d", 4, 1, &[("q1", &["2"]), ("q3", &["1", "2"])]),
+            // q4的3不可能正确，与之前的答题矛盾
+            attempt("e", 5, 2, &[("q2", &["2"]), ("q4", &["3"])]),
+            // q5的2是错误的，只剩3
+            attempt("f", 6, 1, &[("q2", &["2"]), ("q5", &["2"])]),
+            attempt("g", 7, 1, &[("q3", &["1", "3"]), ("q5", &["3"])]),
+        ];
+        let solution = solve(&questions, &attempts);
+        assert_eq!(solution.conflicts, vec!["e"]);
+        let learned = |useful_id: &str| {
+            solution
+                .learned
+                .iter()
+                .find(|q| q.useful_id == useful_id)
+                .unwrap()
+        };
+        let values = |choices: &[AnswerChoice]| {
+            choices
+                .iter()
+                .map(|c| c.choice_value.clone())
+                .collect::<Vec<String>>()
+        };
+        assert_eq!(values(&learned("q1").error_choices), vec!["1"]);
+        assert_eq!(values(&learned("q1").correct_choices), vec!["2"]);
+        assert_eq!(values(&learned("q2").correct_choices), vec!["2"]);
+        assert_eq!(
+            learned("q3").multiple_error_choices,
+            vec![mock_choices(&["1", "2"]), mock_choices(&["1", "3"])]
+        );
+        assert_eq!(values(&learned("q4").correct_choices), vec!["2"]);
+        // 记下的来源是最后推出它的约束
+        assert_eq!(learned("q4").evidence[0].attempt, "b");
+        assert_eq!(values(&learned("q5").error_choices), vec!["1", "2"]);
+        assert_eq!(values(&learned("q5").correct_choices), vec!["3"]);
+        assert_eq!(learned("q5").evidence[0].attempt, "f");
+
+        // 已经知道的不会再出现在结果中
+        let solved = solve(&solution.learned, &attempts);
+        assert!(solved.learned.is_empty());
+    }
+}
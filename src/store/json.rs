@@ -0,0 +1,227 @@
+use std::error::Error;
+use std::fs::OpenOptions;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+use async_trait::async_trait;
+
+use crate::bank::{parse_question, read_bank, read_documents, replace_bank};
+use crate::evidence::Attempt;
+use crate::global::MemoryStoreQuestion;
+use crate::schema::{schema_version, SCHEMA_VERSION};
+use crate::store::{collapse_duplicates, merged, Maintenance, QuestionStore};
+
+/// 保存在一个题库格式（mongoexport --jsonArray）的.json或.zip文件中，
+/// 打开时全部读入内存，每次保存后整个文件重写。
+/// 答题记录追加到同目录下的<文件名>.attempts.jsonl中，每行一次答题
+pub struct JsonStore {
+    path: PathBuf,
+    questions: Mutex<Vec<MemoryStoreQuestion>>,
+    // 打开时文件中旧格式版本的题目数量，读入时已经升级
+    outdated: usize,
+}
+
+impl JsonStore {
+    /// 打开题库文件，文件不存在时从空题库开始，第一次保存时创建
+    pub fn open(path: &Path) -> Result<JsonStore, Box<dyn Error>> {
+        let documents = if path.exists() {
+            read_documents(path)?
+        } else {
+            Vec::new()
+        };
+        let mut outdated = 0;
+        for document in documents.iter() {
+            if schema_version(document)? < SCHEMA_VERSION {
+                outdated += 1;
+            }
+        }
+        let questions = documents
+            .into_iter()
+            .map(parse_question)
+            .collect::<Result<Vec<MemoryStoreQuestion>, Box<dyn Error>>>()?;
+        Ok(JsonStore {
+            path: path.to_path_buf(),
+            questions: Mutex::new(questions),
+            outdated,
+        })
+    }
+
+    fn attempts_path(&self) -> PathBuf {
+        let stem = self
+            .path
+            .file_stem()
+            .map(|stem| stem.to_string_lossy().to_string())
+            .unwrap_or_default();
+        self.path.with_file_name(format!("{}.attempts.jsonl", stem))
+    }
+
+    /// 见bank::replace_bank，写到一半退出时原文件不会损坏
+    fn write(&self, questions: &[MemoryStoreQuestion]) -> Result<(), Box<dyn Error>> {
+        replace_bank(&self.path, questions)
+    }
+}
+
+#[async_trait(?Send)]
+impl QuestionStore for JsonStore {
+    /// 文件中的重复题目合并后、或有旧格式的题目升级后重写文件
+    async fn prepare(&self) -> Result<Maintenance, Box<dyn Error>> {
+        let mut questions = self.questions.lock().expect("json store poisoned");
+        let (collapsed, count) = collapse_duplicates(std::mem::take(&mut *questions));
+        *questions = collapsed;
+        let maintenance = Maintenance {
+            collapsed: count,
+            migrated: self.outdated,
+        };
+        if maintenance != Maintenance::default() {
+            self.write(&questions)?;
+        }
+        Ok(maintenance)
+    }
+
+    async fn find(
+        &self,
+        useful_id: &str,
+        locale: &str,
+    ) -> Result<Option<MemoryStoreQuestion>, Box<dyn Error>> {
+        let questions = self.questions.lock().expect("json store poisoned");
+        Ok(questions
+            .iter()
+            .find(|q| q.useful_id == useful_id && q.locale == locale)
+            .cloned())
+    }
+
+    async fn save(&self, question: &MemoryStoreQuestion) -> Result<(), Box<dyn Error>> {
+        self.save_all(std::slice::from_ref(question)).await
+    }
+
+    async fn save_all(&self, questions: &[MemoryStoreQuestion]) -> Result<(), Box<dyn Error>> {
+        let mut stored = self.questions.lock().expect("json store poisoned");
+        for question in questions {
+            let existing = stored
+                .iter_mut()
+                .find(|q| q.useful_id == question.useful_id && q.locale == question.locale);
+            match existing {
+                Some(existing) => *existing = question.clone(),
+                None => stored.push(question.clone()),
+            }
+        }
+        self.write(&stored)
+    }
+
+    /// 合并前重新读取文件，尽量保留其他进程在此期间写入的了解
+    async fn merge_all(&self, questions: &[MemoryStoreQuestion]) -> Result<(), Box<dyn Error>> {
+        let mut stored = self.questions.lock().expect("json store poisoned");
+        if self.path.exists() {
+            *stored = read_bank(&self.path)?;
+        }
+        for question in questions {
+            let existing = stored
+                .iter_mut()
+                .find(|q| q.useful_id == question.useful_id && q.locale == question.locale);
+            match existing {
+                Some(existing) => *existing = merged(question, Some(existing)),
+                None => stored.push(question.clone()),
+            }
+        }
+        self.write(&stored)
+    }
+
+    async fn all(&self) -> Result<Vec<MemoryStoreQuestion>, Box<dyn Error>> {
+        Ok(self.questions.lock().expect("json store poisoned").clone())
+    }
+
+    /// 只追加不重写，已有的记录不会因为写到一半退出而损坏
+    async fn record_attempt(&self, attempt: &Attempt) -> Result<(), Box<dyn Error>> {
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(self.attempts_path())?;
+        writeln!(file, "{}", serde_json::to_string(attempt)?)?;
+        Ok(())
+    }
+
+    /// 同一iteration_id以后写入的记录为准
+    async fn attempts(&self) -> Result<Vec<Attempt>, Box<dyn Error>> {
+        let path = self.attempts_path();
+        if !path.exists() {
+            return Ok(Vec::new());
+        }
+        let mut attempts: Vec<Attempt> = Vec::new();
+        for line in std::fs::read_to_string(path)?.lines() {
+            if line.trim().is_empty() {
+                continue;
+            }
+            let attempt = serde_json::from_str::<Attempt>(line)?;
+            attempts.retain(|a| a.iteration_id != attempt.iteration_id);
+            attempts.push(attempt);
+        }
+        attempts.sort_by_key(|a| a.timestamp);
+        Ok(attempts)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs::File;
+    use std::path::Path;
+
+    use zip::ZipArchive;
+
+    use crate::bank::read_bank;
+    use crate::global::MemoryStoreQuestion;
+    use crate::response::AnswerChoice;
+    use crate::store::{JsonStore, QuestionStore};
+
+    /// 保存、查找和合并后重新打开文件，结果不变
+    async fn round_trip(path: &Path) {
+        let store = JsonStore::open(path).unwrap();
+        let mut english = MemoryStoreQuestion::mock("q1", &["1", "2"]);
+        store
+            .save_all(&[
+                english.clone(),
+                MemoryStoreQuestion::mock("q1", &["1", "2"]).with_locale("French"),
+            ])
+            .await
+            .unwrap();
+        english.correct_choices = vec![AnswerChoice::from_mock("1")];
+        store.save(&english).await.unwrap();
+        assert_eq!(store.find("q1", "English").await.unwrap(), Some(english));
+        assert_eq!(store.find("q2", "English").await.unwrap(), None);
+
+        let mut answered = MemoryStoreQuestion::mock("q1", &["1", "2"]).with_locale("French");
+        answered.error_choices = vec![AnswerChoice::from_mock("2")];
+        store
+            .merge_all(&[
+                answered.clone(),
+                MemoryStoreQuestion::mock("q2", &["1", "2"]),
+            ])
+            .await
+            .unwrap();
+        let french = store.find("q1", "French").await.unwrap().unwrap();
+        assert_eq!(french.error_choices, answered.error_choices);
+
+        let all = store.all().await.unwrap();
+        assert_eq!(all.len(), 3);
+        assert_eq!(read_bank(path).unwrap(), all);
+        let reopened = JsonStore::open(path).unwrap();
+        assert_eq!(reopened.all().await.unwrap(), all);
+    }
+
+    #[tokio::test]
+    async fn json_store_test() {
+        let dir = std::env::temp_dir().join(format!("wbl-robot-json-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        round_trip(&dir.join("questions.json")).await;
+
+        // .zip中的.json与外层文件同名，不是写入时的临时文件名
+        let zip = dir.join("questions-course.zip");
+        round_trip(&zip).await;
+        let archive = ZipArchive::new(File::open(&zip).unwrap()).unwrap();
+        assert_eq!(
+            archive.file_names().collect::<Vec<&str>>(),
+            vec!["questions-course.json"]
+        );
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,139 @@
+use std::error::Error;
+use std::path::Path;
+
+use async_trait::async_trait;
+
+use crate::config::Config;
+use crate::evidence::Attempt;
+use crate::global::MemoryStoreQuestion;
+use crate::schema::SCHEMA_VERSION;
+
+mod json;
+mod mongo;
+mod sqlite;
+
+pub use json::JsonStore;
+pub use mongo::MongoStore;
+pub use sqlite::SqliteStore;
+
+/// 打开存储时整理的结果
+#[derive(Debug, Default, PartialEq)]
+pub struct Maintenance {
+    /// 被合并掉的重复题目数量
+    pub collapsed: usize,
+    /// 从旧的格式版本升级的题目数量
+    pub migrated: usize,
+}
+
+/// 保存题目的地方，同一题目按useful_id和locale区分
+#[async_trait(?Send)]
+pub trait QuestionStore {
+    /// 打开时整理存储：确保同一题目只能有一条记录，合并旧版本留下的重复记录，将旧格式的记录升级到当前版本
+    async fn prepare(&self) -> Result<Maintenance, Box<dyn Error>>;
+
+    /// 查找某一语言的某一题目
+    async fn find(
+        &self,
+        useful_id: &str,
+        locale: &str,
+    ) -> Result<Option<MemoryStoreQuestion>, Box<dyn Error>>;
+
+    /// 保存题目，已存在的同一题目会被覆盖
+    async fn save(&self, question: &MemoryStoreQuestion) -> Result<(), Box<dyn Error>>;
+
+    /// 保存多个题目，默认逐个保存
+    async fn save_all(&self, questions: &[MemoryStoreQuestion]) -> Result<(), Box<dyn Error>> {
+        for question in questions {
+            self.save(question).await?;
+        }
+        Ok(())
+    }
+
+    /// 将题目和已存储的同一题目合并后保存，每题的合并是原子的，所有题目在一次操作中写入：
+    /// 以新记录为准，错误答案取并集，新记录不知道正确答案时保留已知的正确答案
+    async fn merge_all(&self, questions: &[MemoryStoreQuestion]) -> Result<(), Box<dyn Error>>;
+
+    /// 全部题目
+    async fn all(&self) -> Result<Vec<MemoryStoreQuestion>, Box<dyn Error>>;
+
+    /// 保存一次答题的记录，同一iteration_id的记录会被覆盖
+    async fn record_attempt(&self, attempt: &Attempt) -> Result<(), Box<dyn Error>>;
+
+    /// 全部答题记录，按时间排序
+    async fn attempts(&self) -> Result<Vec<Attempt>, Box<dyn Error>>;
+}
+
+/// 新记录与已存储的记录合并的结果，与merge_all的规则相同
+fn merged(new: &MemoryStoreQuestion, stored: Option<&MemoryStoreQuestion>) -> MemoryStoreQuestion {
+    let mut merged = new.clone();
+    if let Some(stored) = stored {
+        merged.merge_knowledge(stored);
+    }
+    merged
+}
+
+/// 将同一题目的多条记录合并为一条（以第一条为准，了解取并集），返回合并后的题目和被合并掉的数量
+pub(crate) fn collapse_duplicates(
+    questions: Vec<MemoryStoreQuestion>,
+) -> (Vec<MemoryStoreQuestion>, usize) {
+    let mut count = 0;
+    let mut collapsed: Vec<MemoryStoreQuestion> = Vec::new();
+    for question in questions {
+        let existing = collapsed
+            .iter_mut()
+            .find(|q| q.useful_id == question.useful_id && q.locale == question.locale);
+        match existing {
+            Some(existing) => {
+                existing.merge_knowledge(&question);
+                count += 1;
+            }
+            None => collapsed.push(question),
+        }
+    }
+    (collapsed, count)
+}
+
+/// 根据配置中的STORE打开题目的存储
+pub async fn open(config: &Config) -> Result<Box<dyn QuestionStore>, Box<dyn Error>> {
+    let path = Path::new(&config.store_path);
+    let store: Box<dyn QuestionStore> = match config.store.as_str() {
+        "mongodb" => Box::new(
+            MongoStore::connect(
+                &config.mongo_uri,
+                &config.mongo_database,
+                &config.mongo_collection,
+            )
+            .await?,
+        ),
+        "sqlite" => Box::new(SqliteStore::open(path)?),
+        "json" => Box::new(JsonStore::open(path)?),
+        other => return Err(format!("unknown store {}", other).into()),
+    };
+    let maintenance = store.prepare().await?;
+    if maintenance != Maintenance::default() {
+        println!(
+            "collapsed {} duplicate questions, migrated {} questions to schema version {}",
+            maintenance.collapsed, maintenance.migrated, SCHEMA_VERSION
+        );
+    }
+    Ok(store)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use crate::bank::read_bank;
+    use crate::store::collapse_duplicates;
+
+    #[test]
+    fn collapse_duplicates_test() {
+        // 这个题库中有旧版本重复写入的题目
+        let questions = read_bank(Path::new("bank/questions-HRC13003481.zip")).unwrap();
+        assert_eq!(questions.len(), 54);
+        let (collapsed, count) = collapse_duplicates(questions);
+        assert_eq!(collapsed.len(), 30);
+        assert_eq!(count, 24);
+        assert_eq!(collapse_duplicates(collapsed).1, 0);
+    }
+}
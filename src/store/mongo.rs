@@ -0,0 +1,654 @@
+use std::error::Error;
+
+use async_trait::async_trait;
+use mongodb::bson::{self, doc, Bson, Document};
+use mongodb::options::{ClientOptions, FindOptions, IndexOptions, ReplaceOptions};
+use mongodb::{Client, Collection, Database, IndexModel};
+
+use crate::bank::parse_question;
+use crate::config::DEFAULT_LOCALE;
+use crate::evidence::Attempt;
+use crate::global::MemoryStoreQuestion;
+use crate::response::QuestionDetails;
+use crate::schema::SCHEMA_VERSION;
+use crate::store::{collapse_duplicates, Maintenance, QuestionStore};
+
+/// 保存在mongodb的collection中，每题一个文档，答题记录保存在<collection>_attempts中
+pub struct MongoStore {
+    db: Database,
+    coll: Collection<MemoryStoreQuestion>,
+    attempts: Collection<Attempt>,
+}
+
+impl MongoStore {
+    pub async fn connect(
+        uri: &str,
+        database: &str,
+        collection: &str,
+    ) -> Result<MongoStore, Box<dyn Error>> {
+        let mut client_options = ClientOptions::parse(uri).await?;
+        client_options.app_name = Some("wbl robot".to_string());
+        let db_client = Client::with_options(client_options)?;
+        let db = db_client.database(database);
+        Ok(MongoStore {
+            coll: db.collection::<MemoryStoreQuestion>(collection),
+            attempts: db.collection::<Attempt>(&format!("{}_attempts", collection)),
+            db,
+        })
+    }
+
+    /// 不按MemoryStoreQuestion解析的同一collection，旧格式的文档要先升级才能解析
+    fn raw(&self) -> Collection<Document> {
+        self.db.collection::<Document>(self.coll.name())
+    }
+
+    /// 读取符合条件的题目，旧格式的文档先升级到当前版本
+    async fn read(
+        &self,
+        filter: Option<Document>,
+    ) -> Result<Vec<MemoryStoreQuestion>, Box<dyn Error>> {
+        let mut cursor = self.raw().find(filter, None).await?;
+        let mut questions = Vec::new();
+        while cursor.advance().await? {
+            questions.push(parse_document(cursor.deserialize_current()?)?);
+        }
+        Ok(questions)
+    }
+}
+
+fn parse_document(document: Document) -> Result<MemoryStoreQuestion, Box<dyn Error>> {
+    parse_question(Bson::Document(document).into_relaxed_extjson())
+}
+
+/// 在数据库中查找某一语言的某一题目的条件，默认语言同时匹配没有记录语言的旧题目
+fn question_filter(useful_id: &str, locale: &str) -> Document {
+    if locale == DEFAULT_LOCALE {
+        doc! {
+            "useful_id": useful_id,
+            "$or": [{"locale": locale}, {"locale": {"$exists": false}}],
+        }
+    } else {
+        doc! {"useful_id": useful_id, "locale": locale}
+    }
+}
+
+/// 计算选项身份的表达式，与response::choice_id完全相同：
+/// 没有GUID时将ASCII空白换成空格后按空格拆分、去掉空的部分再用一个空格连接，最后转为小写（$toLower只转换ASCII字母），
+/// 即response::normalize_text。$replaceAll需要MongoDB 4.4
+fn choice_id_expr(choice: &str) -> Document {
+    let has = |key: &str| {
+        doc! {"$gt": [{"$strLenCP": {"$ifNull": [format!("{}.{}", choice, key), ""]}}, 0]}
+    };
+    let mut spaced = Bson::String(String::from("$$text"));
+    for find in ["\t", "\n", "\u{c}", "\r"] {
+        spaced = Bson::Document(
+            doc! {"$replaceAll": {"input": spaced, "find": find, "replacement": " "}},
+        );
+    }
+    let normalized = doc! {"$let": {
+        "vars": {"text": {"$ifNull": [format!("{}.choiceValue", choice), ""]}},
+        "in": {"$toLower": {"$reduce": {
+            "input": {"$filter": {
+                "input": {"$split": [spaced, " "]},
+                "as": "word",
+                "cond": {"$ne": ["$$word", ""]},
+            }},
+            "initialValue": "",
+            "in": {"$concat": [
+                "$$value",
+                {"$cond": [{"$eq": ["$$value", ""]}, "", " "]},
+                "$$this",
+            ]},
+        }}},
+    }};
+    doc! {"$switch": {
+        "branches": [
+            {"case": has("answerSysGUID"), "then": format!("{}.answerSysGUID", choice)},
+            {"case": has("answerI18nSysGUID"), "then": format!("{}.answerI18nSysGUID", choice)},
+        ],
+        "default": {"$concat": ["text:", normalized]},
+    }}
+}
+
+/// 一组选项的身份
+fn choice_ids_expr(choices: &str) -> Document {
+    doc! {"$map": {"input": choices, "as": "c", "in": choice_id_expr("$$c")}}
+}
+
+/// 将题目合并进已存储文档的更新管道，与store::merged的规则相同，在服务端一次完成
+fn merge_pipeline(question: &MemoryStoreQuestion) -> Result<Vec<Document>, Box<dyn Error>> {
+    let document = bson::to_document(question)?;
+    let field = |name: &str| {
+        document
+            .get(name)
+            .cloned()
+            .unwrap_or(Bson::Array(Vec::new()))
+    };
+    let mut set = Document::new();
+    for (key, value) in document.iter() {
+        // $literal避免选项文字中的$被当作字段路径
+        set.insert(key, doc! {"$literal": value});
+    }
+    if !question.correct_choices.is_empty() {
+        set.insert(
+            "correct_choices",
+            doc! {"$literal": field("correct_choices")},
+        );
+    } else {
+        set.insert(
+            "correct_choices",
+            doc! {"$ifNull": ["$correct_choices", []]},
+        );
+    }
+    // 新记录不知道所属的课程和测验时保留已存储的
+    for key in ["course_id", "quiz_id", "quiz_sys_guid"] {
+        if document.get_str(key).unwrap_or_default().is_empty() {
+            set.insert(key, doc! {"$ifNull": [format!("${}", key), ""]});
+        }
+    }
+    // 新记录没有题目信息时（例如来自旧题库）保留已存储的
+    if question.details == QuestionDetails::default() {
+        set.insert(
+            "details",
+            doc! {"$ifNull": ["$details", {"$literal": field("details")}]},
+        );
+    }
+    // 按选项的身份去重，同一选项在不同次答题中的其他字段可能不同
+    set.insert(
+        "error_choices",
+        doc! {"$reduce": {
+            "input": {"$concatArrays": [
+                {"$literal": field("error_choices")},
+                {"$ifNull": ["$error_choices", []]},
+            ]},
+            "initialValue": [],
+            "in": {"$cond": [
+                {"$in": [choice_id_expr("$$this"), choice_ids_expr("$$value")]},
+                "$$value",
+                {"$concatArrays": ["$$value", ["$$this"]]},
+            ]},
+        }},
+    );
+    set.insert(
+        "multiple_error_choices",
+        doc! {"$reduce": {
+            "input": {"$concatArrays": [
+                {"$literal": field("multiple_error_choices")},
+                {"$ifNull": ["$multiple_error_choices", []]},
+            ]},
+            "initialValue": [],
+            "in": {"$cond": [
+                {"$anyElementTrue": [{"$map": {
+                    "input": "$$value",
+                    "as": "m",
+                    "in": {"$setEquals": [choice_ids_expr("$$m"), choice_ids_expr("$$this")]},
+                }}]},
+                "$$value",
+                {"$concatArrays": ["$$value", ["$$this"]]},
+            ]},
+        }},
+    );
+    // 了解的来源和尝试过的选项取并集
+    for key in ["evidence", "tried_choices"] {
+        set.insert(
+            key,
+            doc! {"$reduce": {
+                "input": {"$concatArrays": [
+                    {"$literal": field(key)},
+                    {"$ifNull": [format!("${}", key), []]},
+                ]},
+                "initialValue": [],
+                "in": {"$cond": [
+                    {"$in": ["$$this", "$$value"]},
+                    "$$value",
+                    {"$concatArrays": ["$$value", ["$$this"]]},
+                ]},
+            }},
+        );
+    }
+    Ok(vec![doc! {"$set": set}])
+}
+
+#[async_trait(?Send)]
+impl QuestionStore for MongoStore {
+    /// 先将旧格式的题目升级到当前版本，再合并重复的题目，最后建立useful_id和locale的唯一索引
+    async fn prepare(&self) -> Result<Maintenance, Box<dyn Error>> {
+        let raw = self.raw();
+        let mut migrated = 0;
+        let mut cursor = raw
+            .find(
+                doc! {"$or": [
+                    {"schema_version": {"$exists": false}},
+                    {"schema_version": {"$lt": SCHEMA_VERSION}},
+                ]},
+                None,
+            )
+            .await?;
+        while cursor.advance().await? {
+            let document = cursor.deserialize_current()?;
+            let id = document.get("_id").cloned().unwrap_or(Bson::Null);
+            self.coll
+                .replace_one(doc! {"_id": id}, parse_document(document)?, None)
+                .await?;
+            migrated += 1;
+        }
+
+        let mut collapsed = 0;
+        let mut groups = raw
+            .aggregate(
+                vec![
+                    doc! {"$group": {
+                        "_id": {"useful_id": "$useful_id", "locale": "$locale"},
+                        "ids": {"$push": "$_id"},
+                        "count": {"$sum": 1},
+                    }},
+                    doc! {"$match": {"count": {"$gt": 1}}},
+                ],
+                None,
+            )
+            .await?;
+        while groups.advance().await? {
+            let group = groups.deserialize_current()?;
+            let ids = group.get_array("ids")?.clone();
+            let questions = self.read(Some(doc! {"_id": {"$in": &ids}})).await?;
+            let (merged, count) = collapse_duplicates(questions);
+            // 先用合并后的题目替换第一条再删除其余的，中途退出也不会丢失了解
+            if let Some(question) = merged.first() {
+                self.coll
+                    .replace_one(doc! {"_id": &ids[0]}, question, None)
+                    .await?;
+                raw.delete_many(doc! {"_id": {"$in": &ids[1..]}}, None)
+                    .await?;
+                collapsed += count;
+            }
+        }
+
+        let index = IndexModel::builder()
+            .keys(doc! {"useful_id": 1, "locale": 1})
+            .options(
+                IndexOptions::builder()
+                    .unique(true)
+                    .name("useful_id_locale".to_string())
+                    .build(),
+            )
+            .build();
+        self.coll.create_index(index, None).await?;
+        let index = IndexModel::builder()
+            .keys(doc! {"iteration_id": 1})
+            .options(IndexOptions::builder().unique(true).build())
+            .build();
+        self.attempts.create_index(index, None).await?;
+
+        Ok(Maintenance {
+            collapsed,
+            migrated,
+        })
+    }
+
+    async fn find(
+        &self,
+        useful_id: &str,
+        locale: &str,
+    ) -> Result<Option<MemoryStoreQuestion>, Box<dyn Error>> {
+        match self
+            .raw()
+            .find_one(question_filter(useful_id, locale), None)
+            .await?
+        {
+            Some(document) => Ok(Some(parse_document(document)?)),
+            None => Ok(None),
+        }
+    }
+
+    async fn save(&self, question: &MemoryStoreQuestion) -> Result<(), Box<dyn Error>> {
+        let options = ReplaceOptions::builder().upsert(true).build();
+        self.coll
+            .replace_one(
+                question_filter(&question.useful_id, &question.locale),
+                question,
+                options,
+            )
+            .await?;
+        Ok(())
+    }
+
+    /// 所有题目的upsert放在一个update命令中发送，每题的读取、合并和写入在服务端原子地完成
+    async fn merge_all(&self, questions: &[MemoryStoreQuestion]) -> Result<(), Box<dyn Error>> {
+        if questions.is_empty() {
+            return Ok(());
+        }
+        let updates = questions
+            .iter()
+            .map(|q| {
+                Ok(doc! {
+                    "q": question_filter(&q.useful_id, &q.locale),
+                    "u": merge_pipeline(q)?,
+                    "upsert": true,
+                })
+            })
+            .collect::<Result<Vec<Document>, Box<dyn Error>>>()?;
+        let result = self
+            .db
+            .run_command(
+                doc! {"update": self.coll.name(), "updates": updates, "ordered": false},
+                None,
+            )
+            .await?;
+        match result.get_array("writeErrors") {
+            Ok(errors) if !errors.is_empty() => {
+                Err(format!("{} questions were not saved: {:?}", errors.len(), errors).into())
+            }
+            _ => Ok(()),
+        }
+    }
+
+    async fn all(&self) -> Result<Vec<MemoryStoreQuestion>, Box<dyn Error>> {
+        self.read(None).await
+    }
+
+    async fn record_attempt(&self, attempt: &Attempt) -> Result<(), Box<dyn Error>> {
+        let options = ReplaceOptions::builder().upsert(true).build();
+        self.attempts
+            .replace_one(
+                doc! {"iteration_id": &attempt.iteration_id},
+                attempt,
+                options,
+            )
+            .await?;
+        Ok(())
+    }
+
+    async fn attempts(&self) -> Result<Vec<Attempt>, Box<dyn Error>> {
+        let options = FindOptions::builder()
+            .sort(doc! {"timestamp": 1, "iteration_id": 1})
+            .build();
+        let mut cursor = self.attempts.find(None, options).await?;
+        let mut attempts = Vec::new();
+        while cursor.advance().await? {
+            attempts.push(cursor.deserialize_current()?);
+        }
+        Ok(attempts)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use mongodb::bson::{self, Bson, Document};
+    use serde_json::json;
+
+    use crate::global::MemoryStoreQuestion;
+    use crate::response::AnswerChoice;
+    use crate::store::merged;
+    use crate::store::mongo::{merge_pipeline, parse_document};
+
+    type Vars = Vec<(String, Bson)>;
+
+    fn array(value: Bson) -> Vec<Bson> {
+        match value {
+            Bson::Array(array) => array,
+            _ => Vec::new(),
+        }
+    }
+
+    fn string(value: Bson) -> String {
+        match value {
+            Bson::String(value) => value,
+            _ => String::new(),
+        }
+    }
+
+    fn args(args: &Bson, document: &Document, vars: &Vars) -> Vec<Bson> {
+        array(args.clone())
+            .iter()
+            .map(|a| eval(a, document, vars))
+            .collect()
+    }
+
+    fn field(spec: &Bson, key: &str, document: &Document, vars: &Vars) -> Bson {
+        match spec {
+            Bson::Document(spec) => eval(spec.get(key).unwrap_or(&Bson::Null), document, vars),
+            _ => Bson::Null,
+        }
+    }
+
+    fn bind(vars: &Vars, bound: &[(&str, Bson)]) -> Vars {
+        let mut vars = vars.clone();
+        for (name, value) in bound {
+            vars.insert(0, (name.to_string(), value.clone()));
+        }
+        vars
+    }
+
+    /// 按mongodb的语义计算merge_pipeline用到的聚合表达式，没有mongodb也能在测试中运行更新管道
+    fn eval(expression: &Bson, document: &Document, vars: &Vars) -> Bson {
+        match expression {
+            Bson::String(path) if path.starts_with('$') => {
+                let (mut value, rest) = match path.strip_prefix("$$") {
+                    Some(path) => {
+                        let mut parts = path.splitn(2, '.');
+                        let name = parts.next().unwrap_or_default();
+                        let value = vars.iter().find(|(n, _)| n == name).unwrap().1.clone();
+                        (value, parts.next().map(str::to_string))
+                    }
+                    None => (
+                        Bson::Document(document.clone()),
+                        Some(path[1..].to_string()),
+                    ),
+                };
+                for key in rest.iter().flat_map(|rest| rest.split('.')) {
+                    value = match value {
+                        Bson::Document(d) => d.get(key).cloned().unwrap_or(Bson::Null),
+                        _ => Bson::Null,
+                    };
+                }
+                value
+            }
+            Bson::Array(items) => {
+                Bson::Array(items.iter().map(|i| eval(i, document, vars)).collect())
+            }
+            Bson::Document(d) if d.len() == 1 && d.keys().all(|k| k.starts_with('$')) => {
+                let (operator, spec) = d.iter().next().unwrap();
+                operator_eval(operator, spec, document, vars)
+            }
+            Bson::Document(d) => Bson::Document(
+                d.iter()
+                    .map(|(k, v)| (k.clone(), eval(v, document, vars)))
+                    .collect(),
+            ),
+            other => other.clone(),
+        }
+    }
+
+    fn operator_eval(operator: &str, spec: &Bson, document: &Document, vars: &Vars) -> Bson {
+        match operator {
+            "$literal" => spec.clone(),
+            "$ifNull" => {
+                let values = args(spec, document, vars);
+                match &values[0] {
+                    Bson::Null => values[1].clone(),
+                    value => value.clone(),
+                }
+            }
+            "$switch" => {
+                let Bson::Document(spec) = spec else {
+                    unreachable!()
+                };
+                for branch in array(spec.get("branches").cloned().unwrap()) {
+                    if field(&branch, "case", document, vars) == Bson::Boolean(true) {
+                        return field(&branch, "then", document, vars);
+                    }
+                }
+                eval(spec.get("default").unwrap(), document, vars)
+            }
+            "$gt" => {
+                let values = args(spec, document, vars);
+                Bson::Boolean(values[0].as_i32() > values[1].as_i32())
+            }
+            "$eq" => {
+                let values = args(spec, document, vars);
+                Bson::Boolean(values[0] == values[1])
+            }
+            "$ne" => {
+                let values = args(spec, document, vars);
+                Bson::Boolean(values[0] != values[1])
+            }
+            "$strLenCP" => Bson::Int32(string(eval(spec, document, vars)).chars().count() as i32),
+            "$concat" => Bson::String(args(spec, document, vars).into_iter().map(string).collect()),
+            "$toLower" => Bson::String(string(eval(spec, document, vars)).to_ascii_lowercase()),
+            "$replaceAll" => Bson::String(string(field(spec, "input", document, vars)).replace(
+                &string(field(spec, "find", document, vars)),
+                &string(field(spec, "replacement", document, vars)),
+            )),
+            "$split" => {
+                let values = args(spec, document, vars);
+                let (text, separator) = (string(values[0].clone()), string(values[1].clone()));
+                Bson::Array(
+                    text.split(&separator)
+                        .map(|s| Bson::String(s.to_string()))
+                        .collect(),
+                )
+            }
+            "$let" => {
+                let Bson::Document(spec) = spec else {
+                    unreachable!()
+                };
+                let Some(Bson::Document(defined)) = spec.get("vars") else {
+                    unreachable!()
+                };
+                let bound = defined
+                    .iter()
+                    .map(|(name, value)| (name.as_str(), eval(value, document, vars)))
+                    .collect::<Vec<_>>();
+                eval(spec.get("in").unwrap(), document, &bind(vars, &bound))
+            }
+            "$cond" => {
+                let Bson::Array(branches) = spec else {
+                    unreachable!()
+                };
+                if eval(&branches[0], document, vars) == Bson::Boolean(true) {
+                    eval(&branches[1], document, vars)
+                } else {
+                    eval(&branches[2], document, vars)
+                }
+            }
+            "$in" => {
+                let values = args(spec, document, vars);
+                Bson::Boolean(array(values[1].clone()).contains(&values[0]))
+            }
+            "$concatArrays" => Bson::Array(
+                args(spec, document, vars)
+                    .into_iter()
+                    .flat_map(array)
+                    .collect(),
+            ),
+            "$anyElementTrue" => Bson::Boolean(
+                args(spec, document, vars)
+                    .into_iter()
+                    .flat_map(array)
+                    .any(|v| v == Bson::Boolean(true)),
+            ),
+            "$setEquals" => {
+                let values = args(spec, document, vars);
+                let (a, b) = (array(values[0].clone()), array(values[1].clone()));
+                Bson::Boolean(a.iter().all(|x| b.contains(x)) && b.iter().all(|x| a.contains(x)))
+            }
+            "$map" | "$filter" | "$reduce" => {
+                let Bson::Document(spec) = spec else {
+                    unreachable!()
+                };
+                let input = array(eval(spec.get("input").unwrap(), document, vars));
+                let name = spec.get_str("as").unwrap_or("this");
+                match operator {
+                    "$map" => Bson::Array(
+                        input
+                            .into_iter()
+                            .map(|item| {
+                                eval(
+                                    spec.get("in").unwrap(),
+                                    document,
+                                    &bind(vars, &[(name, item)]),
+                                )
+                            })
+                            .collect(),
+                    ),
+                    "$filter" => Bson::Array(
+                        input
+                            .into_iter()
+                            .filter(|item| {
+                                let vars = bind(vars, &[(name, item.clone())]);
+                                eval(spec.get("cond").unwrap(), document, &vars)
+                                    == Bson::Boolean(true)
+                            })
+                            .collect(),
+                    ),
+                    _ => input.into_iter().fold(
+                        eval(spec.get("initialValue").unwrap(), document, vars),
+                        |value, item| {
+                            let vars = bind(vars, &[("value", value), ("this", item)]);
+                            eval(spec.get("in").unwrap(), document, &vars)
+                        },
+                    ),
+                }
+            }
+            _ => panic!("{} is not supported", operator),
+        }
+    }
+
+    /// 在已存储的文档上运行更新管道
+    fn run(pipeline: &[Document], stored: &Document) -> Document {
+        let mut document = stored.clone();
+        for stage in pipeline {
+            let Some(Bson::Document(set)) = stage.get("$set") else {
+                unreachable!()
+            };
+            for (key, expression) in set {
+                document.insert(key, eval(expression, stored, &Vec::new()));
+            }
+        }
+        document
+    }
+
+    fn text_choice(value: &str) -> AnswerChoice {
+        serde_json::from_value(
+            json!({"answerSysGUID": "", "choiceValue": value, "isSelected": false}),
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn merge_pipeline_test() {
+        let mut stored = MemoryStoreQuestion::mock("q1", &["1", "2", "3"]).with_errors(&["1"]);
+        stored.course_id = String::from("HRC1");
+        stored.error_choices.push(text_choice("Yes,  the answer"));
+        stored.error_choices.push(text_choice("ÄNDERN"));
+        stored.multiple_error_choices = vec![vec![text_choice("A  b"), text_choice("C")]];
+        let mut answered =
+            MemoryStoreQuestion::mock("q1", &["1", "2", "3"]).with_errors(&["2", "1"]);
+        // 与已存储的选项只有ASCII的空白和大小写不同
+        answered
+            .error_choices
+            .push(text_choice("  yes, the\tANSWER\r\n"));
+        answered.error_choices.push(text_choice("ändern"));
+        answered.multiple_error_choices = vec![
+            vec![text_choice("c"), text_choice("a\u{c}b")],
+            vec![text_choice("a b")],
+        ];
+
+        let result = run(
+            &merge_pipeline(&answered).unwrap(),
+            &bson::to_document(&stored).unwrap(),
+        );
+        let result = parse_document(result).unwrap();
+        assert_eq!(result, merged(&answered, Some(&stored)));
+        assert_eq!(
+            result
+                .error_choices
+                .iter()
+                .map(|c| c.choice_value.as_str())
+                .collect::<Vec<&str>>(),
+            vec!["2", "1", "  yes, the\tANSWER\r\n", "ändern", "ÄNDERN"]
+        );
+        assert_eq!(result.multiple_error_choices.len(), 2);
+        assert_eq!(result.course_id, "HRC1");
+    }
+}
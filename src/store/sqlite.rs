@@ -0,0 +1,265 @@
+use std::error::Error;
+use std::path::Path;
+use std::sync::Mutex;
+
+use async_trait::async_trait;
+use rusqlite::{params, Connection, OptionalExtension, TransactionBehavior};
+
+use crate::bank::parse_question;
+use crate::evidence::Attempt;
+use crate::global::MemoryStoreQuestion;
+use crate::schema::SCHEMA_VERSION;
+use crate::store::{merged, Maintenance, QuestionStore};
+
+/// 保存在本地的sqlite数据库文件中，每题一行，题目以json保存，不需要额外安装数据库
+pub struct SqliteStore {
+    connection: Mutex<Connection>,
+}
+
+impl SqliteStore {
+    /// 打开数据库文件，文件不存在时创建
+    pub fn open(path: &Path) -> Result<SqliteStore, Box<dyn Error>> {
+        SqliteStore::init(Connection::open(path)?)
+    }
+
+    fn init(connection: Connection) -> Result<SqliteStore, Box<dyn Error>> {
+        connection.execute(
+            "CREATE TABLE IF NOT EXISTS questions (
+                useful_id TEXT NOT NULL,
+                locale TEXT NOT NULL,
+                document TEXT NOT NULL,
+                PRIMARY KEY (useful_id, locale)
+            )",
+            [],
+        )?;
+        connection.execute(
+            "CREATE TABLE IF NOT EXISTS attempts (
+                iteration_id TEXT NOT NULL PRIMARY KEY,
+                timestamp INTEGER NOT NULL,
+                document TEXT NOT NULL
+            )",
+            [],
+        )?;
+        Ok(SqliteStore {
+            connection: Mutex::new(connection),
+        })
+    }
+}
+
+/// 读取一行中保存的题目，旧格式的文档先升级到当前版本
+fn parse_document(document: &str) -> Result<MemoryStoreQuestion, Box<dyn Error>> {
+    parse_question(serde_json::from_str(document)?)
+}
+
+#[async_trait(?Send)]
+impl QuestionStore for SqliteStore {
+    /// 主键保证了同一题目只有一行，只需要在一个事务中将旧格式的题目升级到当前版本
+    async fn prepare(&self) -> Result<Maintenance, Box<dyn Error>> {
+        let mut connection = self.connection.lock().expect("sqlite connection poisoned");
+        let transaction = connection.transaction_with_behavior(TransactionBehavior::Immediate)?;
+        let documents = transaction
+            .prepare(
+                "SELECT rowid, document FROM questions
+                WHERE coalesce(json_extract(document, '$.schema_version'), 1) < ?1",
+            )?
+            .query_map(params![SCHEMA_VERSION], |row| {
+                Ok((row.get::<_, i64>(0)?, row.get::<_, String>(1)?))
+            })?
+            .collect::<Result<Vec<_>, rusqlite::Error>>()?;
+        for (rowid, document) in documents.iter() {
+            transaction.execute(
+                "UPDATE questions SET document = ?1 WHERE rowid = ?2",
+                params![serde_json::to_string(&parse_document(document)?)?, rowid],
+            )?;
+        }
+        transaction.commit()?;
+        Ok(Maintenance {
+            collapsed: 0,
+            migrated: documents.len(),
+        })
+    }
+
+    async fn find(
+        &self,
+        useful_id: &str,
+        locale: &str,
+    ) -> Result<Option<MemoryStoreQuestion>, Box<dyn Error>> {
+        let connection = self.connection.lock().expect("sqlite connection poisoned");
+        let document = connection
+            .query_row(
+                "SELECT document FROM questions WHERE useful_id = ?1 AND locale = ?2",
+                params![useful_id, locale],
+                |row| row.get::<_, String>(0),
+            )
+            .optional()?;
+        match document {
+            Some(document) => Ok(Some(parse_document(&document)?)),
+            None => Ok(None),
+        }
+    }
+
+    async fn save(&self, question: &MemoryStoreQuestion) -> Result<(), Box<dyn Error>> {
+        self.save_all(std::slice::from_ref(question)).await
+    }
+
+    /// 在一个事务中保存，中途出错时不会只保存一部分
+    async fn save_all(&self, questions: &[MemoryStoreQuestion]) -> Result<(), Box<dyn Error>> {
+        let mut connection = self.connection.lock().expect("sqlite connection poisoned");
+        let transaction = connection.transaction()?;
+        for question in questions {
+            transaction.execute(
+                "INSERT OR REPLACE INTO questions (useful_id, locale, document) VALUES (?1, ?2, ?3)",
+                params![
+                    question.useful_id,
+                    question.locale,
+                    serde_json::to_string(question)?
+                ],
+            )?;
+        }
+        transaction.commit()?;
+        Ok(())
+    }
+
+    /// 在一个立即获得写锁的事务中读取、合并、写入，同时运行的其他进程不会在中间写入
+    async fn merge_all(&self, questions: &[MemoryStoreQuestion]) -> Result<(), Box<dyn Error>> {
+        let mut connection = self.connection.lock().expect("sqlite connection poisoned");
+        let transaction = connection.transaction_with_behavior(TransactionBehavior::Immediate)?;
+        for question in questions {
+            let stored = transaction
+                .query_row(
+                    "SELECT document FROM questions WHERE useful_id = ?1 AND locale = ?2",
+                    params![question.useful_id, question.locale],
+                    |row| row.get::<_, String>(0),
+                )
+                .optional()?
+                .map(|document| parse_document(&document))
+                .transpose()?;
+            transaction.execute(
+                "INSERT OR REPLACE INTO questions (useful_id, locale, document) VALUES (?1, ?2, ?3)",
+                params![
+                    question.useful_id,
+                    question.locale,
+                    serde_json::to_string(&merged(question, stored.as_ref()))?
+                ],
+            )?;
+        }
+        transaction.commit()?;
+        Ok(())
+    }
+
+    async fn all(&self) -> Result<Vec<MemoryStoreQuestion>, Box<dyn Error>> {
+        let connection = self.connection.lock().expect("sqlite connection poisoned");
+        let mut statement =
+            connection.prepare("SELECT document FROM questions ORDER BY useful_id, locale")?;
+        let documents = statement
+            .query_map([], |row| row.get::<_, String>(0))?
+            .collect::<Result<Vec<String>, rusqlite::Error>>()?;
+        documents
+            .iter()
+            .map(|document| parse_document(document))
+            .collect()
+    }
+
+    async fn record_attempt(&self, attempt: &Attempt) -> Result<(), Box<dyn Error>> {
+        let connection = self.connection.lock().expect("sqlite connection poisoned");
+        connection.execute(
+            "INSERT OR REPLACE INTO attempts (iteration_id, timestamp, document) VALUES (?1, ?2, ?3)",
+            params![
+                attempt.iteration_id,
+                attempt.timestamp,
+                serde_json::to_string(attempt)?
+            ],
+        )?;
+        Ok(())
+    }
+
+    async fn attempts(&self) -> Result<Vec<Attempt>, Box<dyn Error>> {
+        let connection = self.connection.lock().expect("sqlite connection poisoned");
+        let mut statement =
+            connection.prepare("SELECT document FROM attempts ORDER BY timestamp, iteration_id")?;
+        let documents = statement
+            .query_map([], |row| row.get::<_, String>(0))?
+            .collect::<Result<Vec<String>, rusqlite::Error>>()?;
+        documents
+            .iter()
+            .map(|document| Ok(serde_json::from_str(document)?))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rusqlite::Connection;
+
+    use crate::global::MemoryStoreQuestion;
+    use crate::response::AnswerChoice;
+    use crate::store::{QuestionStore, SqliteStore};
+
+    #[tokio::test]
+    async fn sqlite_store_test() {
+        let store = SqliteStore::init(Connection::open_in_memory().unwrap()).unwrap();
+        let mut english = MemoryStoreQuestion::mock("q1", &["1", "2"]);
+        store
+            .save_all(&[
+                english.clone(),
+                MemoryStoreQuestion::mock("q1", &["1", "2"]).with_locale("French"),
+            ])
+            .await
+            .unwrap();
+        assert_eq!(store.all().await.unwrap().len(), 2);
+
+        english.error_choices = vec![AnswerChoice::from_mock("2")];
+        store.save(&english).await.unwrap();
+        assert_eq!(store.find("q1", "English").await.unwrap(), Some(english));
+        assert_eq!(store.all().await.unwrap().len(), 2);
+        assert_eq!(store.find("q2", "English").await.unwrap(), None);
+    }
+
+    #[tokio::test]
+    async fn sqlite_merge_test() {
+        let store = SqliteStore::init(Connection::open_in_memory().unwrap()).unwrap();
+        let mut stored = MemoryStoreQuestion::mock("q1", &["1", "2"]);
+        stored.correct_choices = vec![AnswerChoice::from_mock("1")];
+        stored.error_choices = vec![AnswerChoice::from_mock("2")];
+        store.save(&stored).await.unwrap();
+
+        let mut answered = MemoryStoreQuestion::mock("q1", &["1", "2"]);
+        answered.error_choices = vec![AnswerChoice::from_mock("3")];
+        store.merge_all(&[answered]).await.unwrap();
+        let merged = store.find("q1", "English").await.unwrap().unwrap();
+        assert_eq!(merged.correct_choices, stored.correct_choices);
+        assert_eq!(
+            merged
+                .error_choices
+                .iter()
+                .map(|c| c.choice_value.as_str())
+                .collect::<Vec<&str>>(),
+            vec!["3", "2"]
+        );
+    }
+
+    #[tokio::test]
+    async fn sqlite_prepare_test() {
+        let store = SqliteStore::init(Connection::open_in_memory().unwrap()).unwrap();
+        let mut document =
+            serde_json::to_value(MemoryStoreQuestion::mock("q1", &["1", "2"])).unwrap();
+        let object = document.as_object_mut().unwrap();
+        object.remove("schema_version");
+        object.remove("locale");
+        store
+            .connection
+            .lock()
+            .unwrap()
+            .execute(
+                "INSERT INTO questions VALUES ('q1', 'English', ?1)",
+                [document.to_string()],
+            )
+            .unwrap();
+        assert_eq!(store.prepare().await.unwrap().migrated, 1);
+        assert_eq!(store.prepare().await.unwrap().migrated, 0);
+        assert_eq!(
+            store.find("q1", "English").await.unwrap(),
+            Some(MemoryStoreQuestion::mock("q1", &["1", "2"]))
+        );
+    }
+}
@@ -1,40 +1,41 @@
 use crate::GlobalData;
+use ::url::form_urlencoded::byte_serialize;
 use std::collections::HashMap;
 
 pub fn iteration_url(global_data: &GlobalData) -> String {
-    let base_url = "https://eygsl.plateau.com/learning/user/api/v1/current-user/quiz";
+    let base_url = quiz_base_url(global_data);
     let mut queries: HashMap<String, String> = HashMap::new();
     queries.insert(
         "studentComponentID".to_string(),
-        global_data.cookie_value("PSA_STUD_CPNT_ID").clone(),
-    );
-    queries.insert(
-        "moduleID".to_string(),
-        global_data.cookie_value("PSA_STUD_CPNT_MOD_ID").clone(),
+        global_data.student_component_id().clone(),
     );
+    queries.insert("moduleID".to_string(), global_data.module_id().clone());
     queries.insert("generateIteration".to_string(), "true".to_string());
-    base_url.to_string()
+    base_url
         + "/"
         + global_data.quiz_id()
-        + "/iteration/English"
+        + "/iteration/"
+        + &*encode(global_data.locale()).replace('+', "%20")
         + "?"
         + &*url_queries(queries)
 }
 
 pub fn start_url(global_data: &GlobalData, question_guid: String) -> String {
-    let base_url = "https://eygsl.plateau.com/learning/user/api/v1/current-user/quiz";
-    base_url.to_string()
+    let base_url = quiz_base_url(global_data);
+    base_url
         + "/"
         + global_data.quiz_id()
         + "/iteration/"
         + global_data.student_guid()
         + "/question/"
         + &*question_guid
+        + "?"
+        + &*url_queries(locale_queries(global_data))
 }
 
 pub fn save_url(global_data: &GlobalData, question_guid: String) -> String {
-    let base_url = "https://eygsl.plateau.com/learning/user/api/v1/current-user/quiz";
-    base_url.to_string()
+    let base_url = quiz_base_url(global_data);
+    base_url
         + "/"
         + global_data.quiz_id()
         + "/iteration/"
@@ -42,11 +43,13 @@ pub fn save_url(global_data: &GlobalData, question_guid: String) -> String {
         + "/question/"
         + &*question_guid
         + "/saveAndNext"
+        + "?"
+        + &*url_queries(locale_queries(global_data))
 }
 
 pub fn submit_url(global_data: &GlobalData, question_guid: String) -> String {
-    let base_url = "https://eygsl.plateau.com/learning/user/api/v1/current-user/quiz";
-    base_url.to_string()
+    let base_url = quiz_base_url(global_data);
+    base_url
         + "/"
         + global_data.quiz_id()
         + "/iteration/"
@@ -54,14 +57,32 @@ pub fn submit_url(global_data: &GlobalData, question_guid: String) -> String {
         + "/question/"
         + &*question_guid
         + "/saveAndSubmit"
+        + "?"
+        + &*url_queries(locale_queries(global_data))
+}
+
+/// 所有quiz接口共同的前缀
+fn quiz_base_url(global_data: &GlobalData) -> String {
+    global_data.api_base().to_string() + "/current-user/quiz"
+}
+
+/// start、saveAndNext和saveAndSubmit都需要带上答题的语言
+fn locale_queries(global_data: &GlobalData) -> HashMap<String, String> {
+    let mut queries: HashMap<String, String> = HashMap::new();
+    queries.insert("localeID".to_string(), global_data.locale().clone());
+    queries
 }
 
 fn url_queries(queries: HashMap<String, String>) -> String {
     let a = queries
         .iter()
-        .map(|(key, value)| key.to_string() + "=" + value);
+        .map(|(key, value)| key.to_string() + "=" + &*encode(value));
     let mut extended = Vec::new();
     extended.extend(a);
 
     extended.join("&")
 }
+
+fn encode(value: &str) -> String {
+    byte_serialize(value.as_bytes()).collect()
+}
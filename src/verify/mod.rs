@@ -0,0 +1,220 @@
+use std::error::Error;
+use std::fmt;
+use std::path::Path;
+
+use crate::bank::{read_bank, replace_bank};
+use crate::evidence::{Attempt, Evidence, Fact};
+use crate::global::{compare_multiple_choices, MemoryStoreQuestion};
+use crate::response::QuestionFormat;
+
+/// 题目记录中自相矛盾或不可能的了解
+#[derive(Debug, PartialEq)]
+pub enum Problem {
+    /// 没有任何选项却记录了了解，无从核对。没有了解的题目下次答到时会补上选项
+    NoChoices,
+    /// 同一选项既是正确的又是错误的
+    CorrectAndError(String),
+    /// 单选题有多个正确选项
+    SeveralCorrect(usize),
+    /// 正确选项不在题目的选项中
+    CorrectNotAChoice(String),
+    /// 多选题已知的正确组合被记为错误组合
+    CorrectEliminated,
+    /// 还不知道正确答案，但所有选项或组合都已被排除，随机选择时没有可选的
+    NothingLeft,
+}
+
+impl fmt::Display for Problem {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Problem::NoChoices => write!(f, "has no choices but has known choices"),
+            Problem::CorrectAndError(value) => {
+                write!(f, "has a choice that is both correct and error: {}", value)
+            }
+            Problem::SeveralCorrect(count) => {
+                write!(f, "is single answer but has {} correct choices", count)
+            }
+            Problem::CorrectNotAChoice(value) => {
+                write!(
+                    f,
+                    "has a correct choice that is not one of its choices: {}",
+                    value
+                )
+            }
+            Problem::CorrectEliminated => {
+                write!(f, "has its correct choices among the error combinations")
+            }
+            Problem::NothingLeft => write!(f, "has every choice eliminated but no correct choice"),
+        }
+    }
+}
+
+/// 检查一个题目的记录是否自洽，返回所有问题
+pub fn verify(question: &MemoryStoreQuestion) -> Vec<Problem> {
+    let mut problems = Vec::new();
+    let knows = !question.correct_choices.is_empty()
+        || !question.error_choices.is_empty()
+        || !question.multiple_error_choices.is_empty();
+    if question.choices.is_empty() && knows {
+        problems.push(Problem::NoChoices);
+    }
+    for choice in question.correct_choices.iter() {
+        if question.error_choices.iter().any(|e| e.same_choice(choice)) {
+            problems.push(Problem::CorrectAndError(choice.choice_value.clone()));
+        }
+        if !question.choices.is_empty() && !question.choices.iter().any(|c| c.same_choice(choice)) {
+            problems.push(Problem::CorrectNotAChoice(choice.choice_value.clone()));
+        }
+    }
+    if question.format == QuestionFormat::MultiChoiceSingleAnswer
+        && question.correct_choices.len() > 1
+    {
+        problems.push(Problem::SeveralCorrect(question.correct_choices.len()));
+    }
+    if !question.correct_choices.is_empty()
+        && question
+            .multiple_error_choices
+            .iter()
+            .any(|m| compare_multiple_choices(m, &question.correct_choices))
+    {
+        problems.push(Problem::CorrectEliminated);
+    }
+    if question.correct_choices.is_empty() && !question.choices.is_empty() {
+        let nothing_left = match question.format {
+            QuestionFormat::MultiChoiceSingleAnswer => question.excluded_errors().is_empty(),
+            QuestionFormat::MultiChoiceMultipleAnswer => !question.combinations_left(),
+        };
+        if nothing_left {
+            problems.push(Problem::NothingLeft);
+        }
+    }
+    problems
+}
+
+/// 去掉了解后的题目，隔离有问题的记录后存储中只保留题目本身，之后重新作答。
+/// 来源和尝试记录换成隔离的标记，最后一次答到这一题及更早的答题不会再被重放或求解，不会重新推出同样的了解
+pub fn isolate(question: &MemoryStoreQuestion, attempts: &[Attempt]) -> MemoryStoreQuestion {
+    let mut isolated = question.clone();
+    isolated.correct_choices.clear();
+    isolated.error_choices.clear();
+    isolated.multiple_error_choices.clear();
+    isolated.tried_choices.clear();
+    isolated.evidence.clear();
+    let last = attempts
+        .iter()
+        .filter(|a| {
+            a.locale == question.locale
+                && a.answers.iter().any(|x| x.useful_id == question.useful_id)
+        })
+        .max_by_key(|a| a.timestamp);
+    if let Some(last) = last {
+        isolated
+            .evidence
+            .push(Evidence::new(Fact::Quarantined, &[], &last.iteration_id));
+    }
+    isolated
+}
+
+/// 将有问题的记录原样追加到隔离文件（题库格式）中，供人工核对，同一题目（useful_id和locale）只保留最新的记录
+pub fn quarantine(path: &Path, questions: &[MemoryStoreQuestion]) -> Result<(), Box<dyn Error>> {
+    let mut quarantined = if path.exists() {
+        read_bank(path)?
+    } else {
+        Vec::new()
+    };
+    for question in questions {
+        quarantined.retain(|q| q.useful_id != question.useful_id || q.locale != question.locale);
+        quarantined.push(question.clone());
+    }
+    replace_bank(path, &quarantined)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::bank::read_bank;
+    use crate::evidence::{
+        before_quarantine, quarantined_until, Attempt, AttemptAnswer, Evidence, Fact, Trial,
+    };
+    use crate::global::{mock_choices, MemoryStoreQuestion};
+    use crate::response::{AnswerChoice, QuestionFormat};
+    use crate::solver::solve;
+    use crate::verify::{isolate, quarantine, verify, Problem};
+
+    #[test]
+    fn verify_test() {
+        let mut single = MemoryStoreQuestion::mock("q1", &["1", "2"]);
+        assert_eq!(verify(&single), vec![]);
+        single.error_choices = mock_choices(&["1", "2"]);
+        assert_eq!(verify(&single), vec![Problem::NothingLeft]);
+        single.correct_choices = mock_choices(&["1", "3"]);
+        assert_eq!(
+            verify(&single),
+            vec![
+                Problem::CorrectAndError(String::from("1")),
+                Problem::CorrectNotAChoice(String::from("3")),
+                Problem::SeveralCorrect(2),
+            ]
+        );
+        assert_eq!(verify(&isolate(&single, &[])), vec![]);
+
+        let mut multiple = MemoryStoreQuestion::mock("q1", &["1", "2"])
+            .with_format(QuestionFormat::MultiChoiceMultipleAnswer);
+        multiple.multiple_error_choices = vec![mock_choices(&["1"]), mock_choices(&["2"])];
+        assert_eq!(verify(&multiple), vec![]);
+        multiple
+            .multiple_error_choices
+            .push(mock_choices(&["2", "1"]));
+        assert_eq!(verify(&multiple), vec![Problem::NothingLeft]);
+        multiple.correct_choices = mock_choices(&["1", "2"]);
+        assert_eq!(verify(&multiple), vec![Problem::CorrectEliminated]);
+
+        multiple.choices.clear();
+        multiple.correct_choices.clear();
+        assert_eq!(verify(&multiple), vec![Problem::NoChoices]);
+        // 隔离后没有了解，补上选项前也不算有问题
+        assert_eq!(verify(&isolate(&multiple, &[])), vec![]);
+    }
+
+    #[test]
+    fn quarantine_test() {
+        let mut single = MemoryStoreQuestion::mock("q1", &["1", "2"]);
+        single.error_choices = mock_choices(&["1", "2"]);
+        single.tried_choices = vec![Trial::new(&AnswerChoice::from_mock("1"), "a1")];
+        let attempt = |id: &str, timestamp: u64| Attempt {
+            iteration_id: id.to_string(),
+            quiz_id: String::from("quiz"),
+            locale: String::from("English"),
+            timestamp,
+            score: 0,
+            questions_correct: 0,
+            answers: vec![AttemptAnswer {
+                useful_id: String::from("q1"),
+                chosen_choices: mock_choices(&["1"]),
+            }],
+        };
+        let attempts = [attempt("a2", 2), attempt("a1", 1)];
+        let isolated = isolate(&single, &attempts);
+        assert!(isolated.tried_choices.is_empty());
+        assert_eq!(
+            isolated.evidence,
+            vec![Evidence::new(Fact::Quarantined, &[], "a2")]
+        );
+        // 隔离前的答题不会再推出了解，之后的答题照常
+        let later = attempt("a3", 3);
+        let questions = [isolated];
+        let until = quarantined_until(&questions, &attempts);
+        assert!(attempts.iter().all(|a| before_quarantine(a, &until)));
+        assert!(!before_quarantine(&later, &until));
+        assert!(solve(&questions, &attempts).learned.is_empty());
+
+        // 同一题目多次隔离只保留最新的记录
+        let path =
+            std::env::temp_dir().join(format!("wbl-robot-quarantine-{}.json", std::process::id()));
+        quarantine(&path, std::slice::from_ref(&single)).unwrap();
+        single.error_choices = mock_choices(&["2", "1"]);
+        quarantine(&path, std::slice::from_ref(&single)).unwrap();
+        let quarantined = read_bank(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(quarantined, vec![single]);
+    }
+}